@@ -0,0 +1,168 @@
+use super::Geometry;
+
+/// Most panels one output can drive
+pub const MAX_PANELS: usize = 8;
+
+/// Clockwise rotation of a panel or the whole canvas
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rotation {
+    Normal,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+impl Rotation {
+    /// Size of the area after rotating one of given size
+    pub(crate) fn rotate_size(self, width: u16, height: u16) -> (u16, u16) {
+        match self {
+            Rotation::Normal | Rotation::Rotate180 => (width, height),
+            Rotation::Rotate90 | Rotation::Rotate270 => (height, width),
+        }
+    }
+
+    /// Converts coordinates within the rotated area to the coordinates
+    /// within the original one of given size
+    pub(crate) fn unrotate(
+        self,
+        width: usize,
+        height: usize,
+        x: usize,
+        y: usize,
+    ) -> (usize, usize) {
+        match self {
+            Rotation::Normal => (x, y),
+            Rotation::Rotate90 => (y, height - 1 - x),
+            Rotation::Rotate180 => (width - 1 - x, height - 1 - y),
+            Rotation::Rotate270 => (width - 1 - y, x),
+        }
+    }
+}
+
+/// Order in which the panels are wired on the canvas
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChainDirection {
+    /// Chain runs along the rows of panels
+    Horizontal,
+    /// Chain runs along the columns of panels
+    Vertical,
+}
+
+/// Arrangement of equal panels connected to one output into a grid.
+///
+/// The chain starts at the top left panel, serpentine wiring reverses every other
+/// row (or column) of panels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChainLayout {
+    pub columns: u8,
+    pub rows: u8,
+    pub direction: ChainDirection,
+    pub serpentine: bool,
+    /// Rotation of every panel, indexed by position on the canvas,
+    /// left to right and top to bottom
+    pub rotations: [Rotation; MAX_PANELS],
+}
+
+impl ChainLayout {
+    pub const fn new(columns: u8, rows: u8, direction: ChainDirection, serpentine: bool) -> Self {
+        ChainLayout {
+            columns,
+            rows,
+            direction,
+            serpentine,
+            rotations: [Rotation::Normal; MAX_PANELS],
+        }
+    }
+
+    /// Only one panel is connected
+    pub const fn single() -> Self {
+        Self::new(1, 1, ChainDirection::Horizontal, false)
+    }
+
+    pub fn panels(&self) -> usize {
+        self.columns as usize * self.rows as usize
+    }
+
+    pub fn is_valid(&self, geometry: &Geometry) -> bool {
+        let panels = self.panels();
+        if panels == 0 || panels > MAX_PANELS {
+            return false;
+        }
+
+        //Rotated panels have to fill the same tile as the rest
+        let tile = Self::tile(geometry, self.rotations[0]);
+        if self.rotations[..panels]
+            .iter()
+            .any(|rotation| Self::tile(geometry, *rotation) != tile)
+        {
+            return false;
+        }
+
+        let (width, height) = (tile.0 as u32, tile.1 as u32);
+        width * self.columns as u32 <= u16::MAX as u32
+            && height * self.rows as u32 <= u16::MAX as u32
+    }
+
+    /// Size of the whole canvas in pixels
+    pub fn size(&self, geometry: &Geometry) -> (u16, u16) {
+        let (width, height) = Self::tile(geometry, self.rotations[0]);
+
+        (width * self.columns as u16, height * self.rows as u16)
+    }
+
+    /// Pixels shifted in for every scan line through all panels
+    pub fn chain_length(&self, geometry: &Geometry) -> usize {
+        self.panels() * geometry.chain_length()
+    }
+
+    /// Port words needed for every bitplane
+    pub fn words(&self, geometry: &Geometry) -> usize {
+        self.panels() * geometry.words()
+    }
+
+    /// Returns scan line, position in the whole chain and whether the pixel
+    /// is in the lower half of its panel
+    pub fn map(&self, geometry: &Geometry, x: usize, y: usize) -> (usize, usize, bool) {
+        let (width, height) = Self::tile(geometry, self.rotations[0]);
+        let (width, height) = (width as usize, height as usize);
+
+        let (column, row) = (x / width, y / height);
+        let rotation = self.rotations[row * self.columns as usize + column];
+        let (x, y) = rotation.unrotate(
+            geometry.width as usize,
+            geometry.height as usize,
+            x % width,
+            y % height,
+        );
+
+        let (scan_row, position, lower_half) = geometry.map(x, y);
+
+        //Data shifted in first ends up in the panel farthest from the controller
+        let panel = self.panels() - 1 - self.chain_index(column, row);
+
+        (
+            scan_row,
+            panel * geometry.chain_length() + position,
+            lower_half,
+        )
+    }
+
+    /// Position of the panel in the chain, 0 is connected to the controller
+    fn chain_index(&self, column: usize, row: usize) -> usize {
+        let (line, position, length) = match self.direction {
+            ChainDirection::Horizontal => (row, column, self.columns as usize),
+            ChainDirection::Vertical => (column, row, self.rows as usize),
+        };
+
+        if self.serpentine && line % 2 == 1 {
+            line * length + length - 1 - position
+        } else {
+            line * length + position
+        }
+    }
+
+    /// Size of the canvas area covered by a panel
+    fn tile(geometry: &Geometry, rotation: Rotation) -> (u16, u16) {
+        rotation.rotate_size(geometry.width, geometry.height)
+    }
+}
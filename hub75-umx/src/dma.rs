@@ -0,0 +1,234 @@
+//! Scan-out driven by TIM1 and DMA1 on STM32F1.
+//!
+//! Every pixel TIM1 compare 1 makes DMA1 channel 2 copy the next port word to the output
+//! register and compare 2 makes DMA1 channel 3 set the clock bit. The CPU only latches the
+//! shifted bitplane, switches row address and times how long it stays lit.
+//!
+//! Available with RawPort only. The application has to call `on_shift_complete`
+//! from `DMA1_CHANNEL3` interrupt and `on_lit_complete` from `TIM1_UP` interrupt.
+
+use core::ptr::{read_volatile, write_volatile};
+
+use super::{scan::ScanState, Hub75, Pins, RawPort};
+
+const RCC_AHBENR: *mut u32 = 0x4002_1014 as *mut u32;
+const RCC_APB2ENR: *mut u32 = 0x4002_1018 as *mut u32;
+const DMA1EN: u32 = 1 << 0;
+const TIM1EN: u32 = 1 << 11;
+
+const DMA1_IFCR: *mut u32 = 0x4002_0004 as *mut u32;
+//Channel 2 is requested by TIM1_CH1, channel 3 by TIM1_CH2
+const DMA1_CCR2: *mut u32 = 0x4002_001C as *mut u32;
+const DMA1_CNDTR2: *mut u32 = 0x4002_0020 as *mut u32;
+const DMA1_CPAR2: *mut u32 = 0x4002_0024 as *mut u32;
+const DMA1_CMAR2: *mut u32 = 0x4002_0028 as *mut u32;
+const DMA1_CCR3: *mut u32 = 0x4002_0030 as *mut u32;
+const DMA1_CNDTR3: *mut u32 = 0x4002_0034 as *mut u32;
+const DMA1_CPAR3: *mut u32 = 0x4002_0038 as *mut u32;
+const DMA1_CMAR3: *mut u32 = 0x4002_003C as *mut u32;
+
+const DMA_EN: u32 = 1 << 0;
+const DMA_TCIE: u32 = 1 << 1;
+const DMA_DIR_FROM_MEMORY: u32 = 1 << 4;
+const DMA_MINC: u32 = 1 << 7;
+const DMA_16BIT: u32 = (1 << 8) | (1 << 10);
+const DMA_32BIT: u32 = (2 << 8) | (2 << 10);
+const DMA_PRIORITY_HIGH: u32 = 2 << 12;
+const DMA_CLEAR_CHANNEL_2: u32 = 0xF << 4;
+const DMA_CLEAR_CHANNEL_3: u32 = 0xF << 8;
+
+const TIM1_CR1: *mut u32 = 0x4001_2C00 as *mut u32;
+const TIM1_DIER: *mut u32 = 0x4001_2C0C as *mut u32;
+const TIM1_SR: *mut u32 = 0x4001_2C10 as *mut u32;
+const TIM1_CNT: *mut u32 = 0x4001_2C24 as *mut u32;
+const TIM1_PSC: *mut u32 = 0x4001_2C28 as *mut u32;
+const TIM1_ARR: *mut u32 = 0x4001_2C2C as *mut u32;
+const TIM1_CCR1: *mut u32 = 0x4001_2C34 as *mut u32;
+const TIM1_CCR2: *mut u32 = 0x4001_2C38 as *mut u32;
+
+const TIM_CEN: u32 = 1 << 0;
+const TIM_OPM: u32 = 1 << 3;
+const TIM_UIE: u32 = 1 << 0;
+const TIM_CC1DE: u32 = 1 << 9;
+const TIM_CC2DE: u32 = 1 << 10;
+
+//TIM1 runs at 72 MHz, one pixel takes 12 ticks
+const PIXEL_PERIOD: u32 = 12;
+//Data word is written at the start of the pixel, clock rises in the middle
+const DATA_COMPARE: u32 = 1;
+const CLOCK_COMPARE: u32 = PIXEL_PERIOD / 2 + 1;
+
+//On-time of the least significant bitplane in TIM1 ticks, 2 us
+const UNIT_TICKS: u32 = 144;
+
+unsafe fn modify(register: *mut u32, set: u32, clear: u32) {
+    write_volatile(register, (read_volatile(register) & !clear) | set);
+}
+
+impl<const PIN_POS: Pins, const WORDS: usize> Hub75<RawPort, PIN_POS, WORDS> {
+    fn bsrr(&self) -> *mut u32 {
+        (self.output_port.address() as u32 + 4) as *mut u32
+    }
+
+    /// Starts continuous scan-out, TIM1 and DMA1 channels 2 and 3 are used exclusively.
+    /// Hub75 must not be moved while scan-out is running.
+    pub fn start_dma(&mut self) {
+        if self.scan.running {
+            return;
+        }
+
+        unsafe {
+            modify(RCC_AHBENR, DMA1EN, 0);
+            modify(RCC_APB2ENR, TIM1EN, 0);
+
+            write_volatile(TIM1_CR1, 0);
+            write_volatile(TIM1_PSC, 0);
+            write_volatile(TIM1_CCR1, DATA_COMPARE);
+            write_volatile(TIM1_CCR2, CLOCK_COMPARE);
+
+            write_volatile(DMA1_CPAR2, self.output_port.address() as u32);
+            write_volatile(DMA1_CPAR3, self.bsrr() as u32);
+            write_volatile(DMA1_CMAR3, &self.clock_set as *const u32 as u32);
+        }
+
+        self.scan = ScanState::new();
+        self.scan.running = true;
+        self.start_shift();
+    }
+
+    pub fn dma_running(&self) -> bool {
+        self.scan.running
+    }
+
+    /// Stops scan-out and disables the output
+    pub fn stop_dma(&mut self) {
+        self.scan.running = false;
+
+        unsafe {
+            write_volatile(TIM1_CR1, 0);
+            write_volatile(TIM1_DIER, 0);
+            write_volatile(DMA1_CCR2, 0);
+            write_volatile(DMA1_CCR3, 0);
+            write_volatile(self.bsrr(), Self::PINS.oe as u32);
+        }
+    }
+
+    /// Last pixel of a bitplane was clocked in, latches it and lights the row
+    pub fn on_shift_complete(&mut self) {
+        unsafe {
+            write_volatile(DMA1_IFCR, DMA_CLEAR_CHANNEL_2 | DMA_CLEAR_CHANNEL_3);
+            write_volatile(TIM1_CR1, 0);
+            write_volatile(TIM1_DIER, 0);
+            write_volatile(DMA1_CCR2, 0);
+            write_volatile(DMA1_CCR3, 0);
+        }
+
+        if !self.scan.running {
+            return;
+        }
+
+        let address_mask = (Self::PINS.a | Self::PINS.b | Self::PINS.c) as u32;
+        let address = Self::row_address(self.scan.row) as u32;
+        let clock = Self::PINS.clock as u32;
+        let latch = Self::PINS.latch as u32;
+
+        unsafe {
+            //Upper half of BSRR resets pins, lower half sets them
+            write_volatile(self.bsrr(), latch | (clock << 16));
+            write_volatile(
+                self.bsrr(),
+                address | ((address_mask & !address) << 16) | (latch << 16),
+            );
+        }
+
+        let on_time = (UNIT_TICKS << self.scan.plane) * self.brightness as u32 / 255;
+        if on_time == 0 {
+            self.advance_scan();
+            self.start_shift();
+            return;
+        }
+
+        unsafe {
+            write_volatile(self.bsrr(), (Self::PINS.oe as u32) << 16);
+        }
+        Self::start_lit_timer(on_time);
+    }
+
+    /// TIM1 calls on_lit_complete after given number of ticks
+    fn start_lit_timer(ticks: u32) {
+        unsafe {
+            write_volatile(TIM1_CNT, 0);
+            write_volatile(TIM1_ARR, ticks);
+            write_volatile(TIM1_SR, 0);
+            write_volatile(TIM1_DIER, TIM_UIE);
+            write_volatile(TIM1_CR1, TIM_OPM | TIM_CEN);
+        }
+    }
+
+    /// Bitplane was shown long enough, starts shifting in the next one
+    pub fn on_lit_complete(&mut self) {
+        unsafe {
+            write_volatile(TIM1_SR, 0);
+            write_volatile(TIM1_DIER, 0);
+            write_volatile(self.bsrr(), Self::PINS.oe as u32);
+        }
+
+        if !self.scan.running {
+            return;
+        }
+
+        self.advance_scan();
+        self.start_shift();
+    }
+
+    fn advance_scan(&mut self) {
+        self.scan
+            .advance(self.planes, self.geometry.scan_rows as usize);
+    }
+
+    fn start_shift(&mut self) {
+        //Geometry is being changed, tries again later with the output disabled
+        let planes = self.scan_planes();
+        if planes == 0 {
+            Self::start_lit_timer(UNIT_TICKS);
+            return;
+        }
+
+        let geometry = self.geometry;
+        let layout = self.layout;
+        if self.scan.row >= geometry.scan_rows as usize || self.scan.plane >= planes {
+            self.scan.restart();
+        }
+
+        let words = Self::chain(
+            &self.words,
+            &geometry,
+            &layout,
+            self.scan.plane,
+            self.scan.row,
+        );
+
+        unsafe {
+            write_volatile(DMA1_IFCR, DMA_CLEAR_CHANNEL_2 | DMA_CLEAR_CHANNEL_3);
+
+            write_volatile(DMA1_CMAR2, words.as_ptr() as u32);
+            write_volatile(DMA1_CNDTR2, words.len() as u32);
+            write_volatile(
+                DMA1_CCR2,
+                DMA_DIR_FROM_MEMORY | DMA_MINC | DMA_16BIT | DMA_PRIORITY_HIGH | DMA_EN,
+            );
+
+            write_volatile(DMA1_CNDTR3, words.len() as u32);
+            write_volatile(
+                DMA1_CCR3,
+                DMA_DIR_FROM_MEMORY | DMA_32BIT | DMA_PRIORITY_HIGH | DMA_TCIE | DMA_EN,
+            );
+
+            write_volatile(TIM1_CNT, 0);
+            write_volatile(TIM1_ARR, PIXEL_PERIOD - 1);
+            write_volatile(TIM1_SR, 0);
+            write_volatile(TIM1_DIER, TIM_CC1DE | TIM_CC2DE);
+            write_volatile(TIM1_CR1, TIM_CEN);
+        }
+    }
+}
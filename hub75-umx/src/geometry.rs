@@ -0,0 +1,99 @@
+/// Order of pixels in the shift register chain when more rows than one
+/// are connected to every scan line
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mapping {
+    /// Every scan line drives a single row of each half
+    Direct,
+    /// Chain alternates segments of given width taken from every block of rows,
+    /// starting with the bottom one
+    Stripe(u8),
+    /// Like Stripe, but segments of every other block are wired in reverse
+    Zigzag(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Geometry {
+    pub width: u16,
+    pub height: u16,
+    /// Rows driven at once by the address lines, e.g. 8 for 1/8 scan
+    pub scan_rows: u16,
+    pub mapping: Mapping,
+}
+
+impl Geometry {
+    //Address lines A-E
+    const MAX_SCAN_ROWS: u16 = 32;
+
+    pub const fn new(width: u16, height: u16, scan_rows: u16, mapping: Mapping) -> Self {
+        Geometry {
+            width,
+            height,
+            scan_rows,
+            mapping,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        if self.width == 0
+            || self.height == 0
+            || self.height % 2 != 0
+            || !self.scan_rows.is_power_of_two()
+            || self.scan_rows > Self::MAX_SCAN_ROWS
+            || (self.height / 2) % self.scan_rows != 0
+        {
+            return false;
+        }
+
+        match self.mapping {
+            Mapping::Direct => self.blocks() == 1,
+            Mapping::Stripe(segment) | Mapping::Zigzag(segment) => {
+                segment != 0 && self.width % segment as u16 == 0
+            }
+        }
+    }
+
+    /// Number of rows in each half connected to one scan line
+    pub fn blocks(&self) -> usize {
+        (self.height / 2 / self.scan_rows) as usize
+    }
+
+    /// Pixels shifted in for every scan line
+    pub fn chain_length(&self) -> usize {
+        self.width as usize * self.blocks()
+    }
+
+    /// Port words needed for every bitplane
+    pub fn words(&self) -> usize {
+        self.scan_rows as usize * self.chain_length()
+    }
+
+    /// Returns scan line, position in the chain and whether the pixel is in the lower half
+    pub fn map(&self, x: usize, y: usize) -> (usize, usize, bool) {
+        let half_height = self.height as usize / 2;
+        let scan_rows = self.scan_rows as usize;
+
+        let lower_half = y >= half_height;
+        let y = y % half_height;
+        let row = y % scan_rows;
+
+        let column = match self.mapping {
+            Mapping::Direct => x,
+            Mapping::Stripe(segment) | Mapping::Zigzag(segment) => {
+                let segment = segment as usize;
+                let blocks = self.blocks();
+                let block = blocks - 1 - y / scan_rows;
+
+                let mut offset = x % segment;
+                if let Mapping::Zigzag(_) = self.mapping {
+                    if block % 2 == 1 {
+                        offset = segment - 1 - offset;
+                    }
+                }
+
+                (x / segment) * segment * blocks + block * segment + offset
+            }
+        };
+
+        (row, column, lower_half)
+    }
+}
@@ -11,72 +11,84 @@
 // - Optimization of PWM loop
 // - Optimization of I/O for stm32 platform
 // - Mapping pixels for 1/8 driving mode matrices
+// - Panel geometry and multiplexing selected at runtime
 // - Upgrade to Embedded-graphics-07
-// - Using const generics to enable variable length of matrix
+// - Using const generics to set framebuffer size
+// - Binary code modulation scan-out
+// - DMA scan-out for stm32f1
+// - Selectable gamma correction and white balance
+// - Chaining several panels into one canvas
+// - Display rotation and mirroring
+// - Output through a pluggable port writer
 
 
 #![no_std]
 #![feature(const_generics)]
 
+use core::mem::MaybeUninit;
+use core::ptr::{addr_of_mut, read_volatile, write_volatile};
+use core::sync::atomic::{compiler_fence, Ordering};
 use core::usize;
 
+#[cfg(not(feature = "bcm"))]
 use embedded_hal::blocking::delay::DelayUs;
-use embedded_hal::digital::v2::OutputPin;
 
-#[cfg(feature = "size-64x64")]
-const NUM_ROWS: usize = 32;
-#[cfg(not(feature = "size-64x64"))]
-const NUM_ROWS: usize = 16;
+mod chain;
+#[cfg(feature = "stm32f1-dma")]
+mod dma;
+mod geometry;
+#[cfg(test)]
+mod mock;
+mod orientation;
+mod port;
+#[cfg(any(feature = "bcm", feature = "stm32f1-dma"))]
+mod scan;
+
+pub use chain::{ChainDirection, ChainLayout, Rotation, MAX_PANELS};
+pub use geometry::{Geometry, Mapping};
+pub use orientation::Orientation;
+pub use port::{OutputPort, PinPort, RawPort, PIN_PORT_POS};
+
+//Maximum color depth per channel. The framebuffer of WORDS is allocated at once, geometries
+//too big for this many bitplanes get fewer of them, every bitplane takes width * height bytes
+#[cfg(not(feature = "bcm"))]
+const PLANES: usize = 4;
+#[cfg(feature = "bcm")]
+const PLANES: usize = 6;
 
 // gamma 2.8
-// const GAMMA8: [u8; 256] = [
-//     0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1,
-//     1, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 5, 5, 5,
-//     5, 6, 6, 6, 6, 7, 7, 7, 7, 8, 8, 8, 9, 9, 9, 10, 10, 10, 11, 11, 11, 12, 12, 13, 13, 13, 14,
-//     14, 15, 15, 16, 16, 17, 17, 18, 18, 19, 19, 20, 20, 21, 21, 22, 22, 23, 24, 24, 25, 25, 26, 27,
-//     27, 28, 29, 29, 30, 31, 32, 32, 33, 34, 35, 35, 36, 37, 38, 39, 39, 40, 41, 42, 43, 44, 45, 46,
-//     47, 48, 49, 50, 50, 51, 52, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63, 64, 66, 67, 68, 69, 70, 72,
-//     73, 74, 75, 77, 78, 79, 81, 82, 83, 85, 86, 87, 89, 90, 92, 93, 95, 96, 98, 99, 101, 102, 104,
-//     105, 107, 109, 110, 112, 114, 115, 117, 119, 120, 122, 124, 126, 127, 129, 131, 133, 135, 137,
-//     138, 140, 142, 144, 146, 148, 150, 152, 154, 156, 158, 160, 162, 164, 167, 169, 171, 173, 175,
-//     177, 180, 182, 184, 186, 189, 191, 193, 196, 198, 200, 203, 205, 208, 210, 213, 215, 218, 220,
-//     223, 225, 228, 231, 233, 236, 239, 241, 244, 247, 249, 252, 255,
-// ];
-
-//no corr
-// const GAMMA8: [u8; 256] = [
-//     0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25,
-//     26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47, 48, 49,
-//     50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63, 64, 65, 66, 67, 68, 69, 70, 71, 72, 73,
-//     74, 75, 76, 77, 78, 79, 80, 81, 82, 83, 84, 85, 86, 87, 88, 89, 90, 91, 92, 93, 94, 95, 96, 97,
-//     98, 99, 100, 101, 102, 103, 104, 105, 106, 107, 108, 109, 110, 111, 112, 113, 114, 115, 116,
-//     117, 118, 119, 120, 121, 122, 123, 124, 125, 126, 127, 128, 129, 130, 131, 132, 133, 134, 135,
-//     136, 137, 138, 139, 140, 141, 142, 143, 144, 145, 146, 147, 148, 149, 150, 151, 152, 153, 154,
-//     155, 156, 157, 158, 159, 160, 161, 162, 163, 164, 165, 166, 167, 168, 169, 170, 171, 172, 173,
-//     174, 175, 176, 177, 178, 179, 180, 181, 182, 183, 184, 185, 186, 187, 188, 189, 190, 191, 192,
-//     193, 194, 195, 196, 197, 198, 199, 200, 201, 202, 203, 204, 205, 206, 207, 208, 209, 210, 211,
-//     212, 213, 214, 215, 216, 217, 218, 219, 220, 221, 222, 223, 224, 225, 226, 227, 228, 229, 230,
-//     231, 232, 233, 234, 235, 236, 237, 238, 239, 240, 241, 242, 243, 244, 245, 246, 247, 248, 249,
-//     250, 251, 252, 253, 254, 255,
-// ];
-
-
-//gamma 2.0
-const GAMMA8: [u8; 256] = [
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4,
-    4, 4, 5, 5, 5, 5, 6, 6, 6, 7, 7, 7, 8, 8, 8, 9, 9, 9, 10, 10, 11, 11, 11, 12, 12, 13, 13, 14,
-    14, 15, 15, 16, 16, 17, 17, 18, 18, 19, 19, 20, 20, 21, 21, 22, 23, 23, 24, 24, 25, 26, 26, 27,
-    28, 28, 29, 30, 30, 31, 32, 32, 33, 34, 35, 35, 36, 37, 38, 38, 39, 40, 41, 42, 42, 43, 44, 45,
-    46, 47, 47, 48, 49, 50, 51, 52, 53, 54, 55, 56, 56, 57, 58, 59, 60, 61, 62, 63, 64, 65, 66, 67,
-    68, 69, 70, 71, 73, 74, 75, 76, 77, 78, 79, 80, 81, 82, 84, 85, 86, 87, 88, 89, 91, 92, 93, 94,
-    95, 97, 98, 99, 100, 102, 103, 104, 105, 107, 108, 109, 111, 112, 113, 115, 116, 117, 119, 120,
-    121, 123, 124, 126, 127, 128, 130, 131, 133, 134, 136, 137, 139, 140, 142, 143, 145, 146, 148,
-    149, 151, 152, 154, 155, 157, 158, 160, 162, 163, 165, 166, 168, 170, 171, 173, 175, 176, 178,
-    180, 181, 183, 185, 186, 188, 190, 192, 193, 195, 197, 199, 200, 202, 204, 206, 207, 209, 211,
-    213, 215, 217, 218, 220, 222, 224, 226, 228, 230, 232, 233, 235, 237, 239, 241, 243, 245, 247,
-    249, 251, 253, 255,
+pub const GAMMA_2_8: [u8; 256] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1,
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 5, 5, 5,
+    5, 6, 6, 6, 6, 7, 7, 7, 7, 8, 8, 8, 9, 9, 9, 10, 10, 10, 11, 11, 11, 12, 12, 13, 13, 13, 14,
+    14, 15, 15, 16, 16, 17, 17, 18, 18, 19, 19, 20, 20, 21, 21, 22, 22, 23, 24, 24, 25, 25, 26, 27,
+    27, 28, 29, 29, 30, 31, 32, 32, 33, 34, 35, 35, 36, 37, 38, 39, 39, 40, 41, 42, 43, 44, 45, 46,
+    47, 48, 49, 50, 50, 51, 52, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63, 64, 66, 67, 68, 69, 70, 72,
+    73, 74, 75, 77, 78, 79, 81, 82, 83, 85, 86, 87, 89, 90, 92, 93, 95, 96, 98, 99, 101, 102, 104,
+    105, 107, 109, 110, 112, 114, 115, 117, 119, 120, 122, 124, 126, 127, 129, 131, 133, 135, 137,
+    138, 140, 142, 144, 146, 148, 150, 152, 154, 156, 158, 160, 162, 164, 167, 169, 171, 173, 175,
+    177, 180, 182, 184, 186, 189, 191, 193, 196, 198, 200, 203, 205, 208, 210, 213, 215, 218, 220,
+    223, 225, 228, 231, 233, 236, 239, 241, 244, 247, 249, 252, 255,
 ];
 
+//No correction, computed like gamma_2_0 so the table takes no flash
+pub fn linear() -> [u8; 256] {
+    let mut table = [0; 256];
+    for (i, value) in table.iter_mut().enumerate() {
+        *value = i as u8;
+    }
+    table
+}
+
+//gamma 2.0, i * i / 255 rounded
+pub fn gamma_2_0() -> [u8; 256] {
+    let mut table = [0; 256];
+    for (i, value) in table.iter_mut().enumerate() {
+        *value = ((i * i + 127) / 255) as u8;
+    }
+    table
+}
+
 #[derive(PartialEq, Eq)]
 pub struct Pins {
     pub r1: u16,
@@ -88,22 +100,50 @@ pub struct Pins {
     pub a: u16,
     pub b: u16,
     pub c: u16,
+    pub d: u16,
+    pub e: u16,
     pub clock: u16,
     pub latch: u16,
     pub oe: u16,
 }
-pub struct Hub75<const PIN_POS: Pins, const ROW_LENGTH: usize> {
-    //r1, g1, b1, r2, g2, b2, column, row
-    #[cfg(not(feature = "stripe-multiplexing"))]
-    data: [[(u8, u8, u8, u8, u8, u8); ROW_LENGTH]; NUM_ROWS],
-
-    #[cfg(feature = "stripe-multiplexing")]
-    data: [[(u8, u8, u8, u8, u8, u8); ROW_LENGTH]; NUM_ROWS / 2],
 
-    output_port: *mut u16,
+/// Geometry or chain layout is not consistent or doesn't fit into the framebuffer
+#[derive(Debug)]
+pub struct InvalidGeometry;
+
+pub struct Hub75<PORT: OutputPort, const PIN_POS: Pins, const WORDS: usize> {
+    //Port words with color bits, address of the previous row and OE set, indexed by
+    //bitplane, scan line and position in the chain of all panels. Precomputed when
+    //drawing, so scan-out only copies them to the port
+    words: [u16; WORDS],
+
+    //Geometry of a single panel
+    geometry: Geometry,
+    layout: ChainLayout,
+    orientation: Orientation,
+    //Bitplanes fitting into the framebuffer with current geometry
+    planes: usize,
+
+    output_port: PORT,
+    brightness: u8,
+    gamma: [u8; 256],
+    //r, g, b gains, 255 leaves the channel unchanged
+    white_balance: [u8; 3],
+
+    //DMA reads clock bit for the set/reset register from here
+    #[cfg(feature = "stm32f1-dma")]
+    clock_set: u32,
+    #[cfg(any(feature = "bcm", feature = "stm32f1-dma"))]
+    scan: scan::ScanState,
+    //Full scan-outs per second and the time of every bitplane in output_step,
+    //recomputed whenever they change with geometry or brightness
+    #[cfg(feature = "bcm")]
+    refresh_rate: u32,
+    #[cfg(feature = "bcm")]
+    plane_times: [scan::PlaneTime; PLANES],
 }
 
-impl<const PIN_POS: Pins, const ROW_LENGTH: usize> Hub75<PIN_POS, ROW_LENGTH> {
+impl<PORT: OutputPort, const PIN_POS: Pins, const WORDS: usize> Hub75<PORT, PIN_POS, WORDS> {
     const PINS: Pins = Pins {
         r1: 1 << PIN_POS.r1,
         g1: 1 << PIN_POS.g1,
@@ -114,118 +154,406 @@ impl<const PIN_POS: Pins, const ROW_LENGTH: usize> Hub75<PIN_POS, ROW_LENGTH> {
         a: 1 << PIN_POS.a,
         b: 1 << PIN_POS.b,
         c: 1 << PIN_POS.c,
+        d: 1 << PIN_POS.d,
+        e: 1 << PIN_POS.e,
         clock: 1 << PIN_POS.clock,
         latch: 1 << PIN_POS.latch,
         oe: 1 << PIN_POS.oe,
     };
 
-    /// TODO: Write better documentation
-    /// color_pins are numbers of pins r1, g1, b1, r2, g2, b2, A, B, C, clock, latch, OE
-    pub fn new(output_port: &mut u16) -> Self {
+    //r1, g1, b1, r2, g2, b2 in the order of packed color bits
+    const COLOR_PINS: [u16; 6] = [
+        Self::PINS.r1,
+        Self::PINS.g1,
+        Self::PINS.b1,
+        Self::PINS.r2,
+        Self::PINS.g2,
+        Self::PINS.b2,
+    ];
+
+    /// PIN_POS are numbers of pins r1, g1, b1, r2, g2, b2, A-E, clock, latch, OE
+    /// of the port written through output_port, e.g. RawPort for a GPIO register
+    /// or PinPort with PIN_PORT_POS for separate pins.
+    /// WORDS is the framebuffer size, color depth depends on how many bitplanes of the
+    /// geometry fit into it. A single panel is connected, see `set_chain`
+    pub fn new(output_port: PORT, geometry: Geometry) -> Result<Self, InvalidGeometry> {
+        let mut hub75 = MaybeUninit::uninit();
+        Self::init(&mut hub75, output_port, geometry)?;
+
+        Ok(unsafe { hub75.assume_init() })
+    }
+
+    /// Same as `new`, but builds the display in place, e.g. in a static.
+    /// The framebuffer is too big to be moved around on the stack of a small MCU
+    pub fn init(
+        hub75: &mut MaybeUninit<Self>,
+        output_port: PORT,
+        geometry: Geometry,
+    ) -> Result<&mut Self, InvalidGeometry> {
+        let ptr = hub75.as_mut_ptr();
+
+        //Every field is written before the reference is taken, set_geometry clears the words
+        let hub75 = unsafe {
+            addr_of_mut!((*ptr).words).write_bytes(0, 1);
+            addr_of_mut!((*ptr).geometry).write(geometry);
+            addr_of_mut!((*ptr).layout).write(ChainLayout::single());
+            addr_of_mut!((*ptr).orientation).write(Orientation::normal());
+            addr_of_mut!((*ptr).planes).write(0);
+            addr_of_mut!((*ptr).output_port).write(output_port);
+            addr_of_mut!((*ptr).brightness).write(255);
+            addr_of_mut!((*ptr).gamma).write(gamma_2_0());
+            addr_of_mut!((*ptr).white_balance).write([255; 3]);
+            #[cfg(feature = "stm32f1-dma")]
+            addr_of_mut!((*ptr).clock_set).write(Self::PINS.clock as u32);
+            #[cfg(any(feature = "bcm", feature = "stm32f1-dma"))]
+            addr_of_mut!((*ptr).scan).write(scan::ScanState::new());
+            #[cfg(feature = "bcm")]
+            addr_of_mut!((*ptr).refresh_rate).write(120);
+            #[cfg(feature = "bcm")]
+            addr_of_mut!((*ptr).plane_times).write([scan::PlaneTime::default(); PLANES]);
+
+            &mut *ptr
+        };
+        hub75.set_geometry(geometry)?;
+
+        Ok(hub75)
+    }
 
-        #[cfg(not(feature = "stripe-multiplexing"))]
-        let data = [[(0, 0, 0, 0, 0, 0); ROW_LENGTH]; NUM_ROWS];
-        #[cfg(feature = "stripe-multiplexing")]
-        let data = [[(0, 0, 0, 0, 0, 0); ROW_LENGTH]; NUM_ROWS / 2];
+    /// Changes panel size and multiplexing, the display is cleared
+    pub fn set_geometry(&mut self, geometry: Geometry) -> Result<(), InvalidGeometry> {
+        self.set_chain(geometry, self.layout)
+    }
 
-        Self {
-            data,
-            output_port,
+    /// Changes arrangement of the chained panels, the display is cleared
+    pub fn set_layout(&mut self, layout: ChainLayout) -> Result<(), InvalidGeometry> {
+        self.set_chain(self.geometry, layout)
+    }
+
+    /// Changes geometry of every panel and their arrangement at once,
+    /// the display is cleared.
+    ///
+    /// Scan-out interrupting the change shows nothing, it never sees
+    /// geometry and layout from different settings
+    pub fn set_chain(
+        &mut self,
+        geometry: Geometry,
+        layout: ChainLayout,
+    ) -> Result<(), InvalidGeometry> {
+        if !geometry.is_valid() || !layout.is_valid(&geometry) || layout.words(&geometry) > WORDS
+        {
+            return Err(InvalidGeometry);
         }
+
+        //Pauses scan-out, see scan_planes
+        unsafe { write_volatile(&mut self.planes, 0) };
+        compiler_fence(Ordering::SeqCst);
+
+        self.geometry = geometry;
+        self.layout = layout;
+        let planes = (WORDS / layout.words(&geometry)).min(PLANES);
+
+        #[cfg(any(feature = "bcm", feature = "stm32f1-dma"))]
+        self.scan.restart();
+        #[cfg(feature = "bcm")]
+        self.update_plane_times(planes);
+
+        self.fill(0, planes);
+
+        compiler_fence(Ordering::SeqCst);
+        unsafe { write_volatile(&mut self.planes, planes) };
+
+        Ok(())
+    }
+
+    /// Bitplanes to scan out, 0 while set_chain is changing the geometry.
+    /// Geometry and layout read after it belong to the same settings
+    fn scan_planes(&self) -> usize {
+        let planes = unsafe { read_volatile(&self.planes) };
+        compiler_fence(Ordering::SeqCst);
+
+        planes
+    }
+
+    pub fn geometry(&self) -> Geometry {
+        self.geometry
+    }
+
+    pub fn layout(&self) -> ChainLayout {
+        self.layout
+    }
+
+    /// Rotates and mirrors the drawing area.
+    ///
+    /// Applies to pixels drawn afterwards
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        self.orientation = orientation;
+    }
+
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    /// Bits per color channel with current geometry
+    pub fn color_depth(&self) -> usize {
+        self.planes
+    }
+
+    fn row_address(row: usize) -> u16 {
+        let mut address = 0;
+
+        if row & 1 != 0 {
+            address += Self::PINS.a;
+        }
+        if row & 2 != 0 {
+            address += Self::PINS.b;
+        }
+        if row & 4 != 0 {
+            address += Self::PINS.c;
+        }
+        if row & 8 != 0 {
+            address += Self::PINS.d;
+        }
+        if row & 16 != 0 {
+            address += Self::PINS.e;
+        }
+
+        address
+    }
+
+    /// Control lines set while shifting in the row: address of the previous row,
+    /// which may still be displayed at that time, and disabled output
+    fn shift_control(row: usize, scan_rows: usize) -> u16 {
+        Self::row_address((row + scan_rows - 1) % scan_rows) | Self::PINS.oe
+    }
+
+    /// Words shifted in for the scan line in given bitplane
+    fn chain<'a>(
+        words: &'a [u16; WORDS],
+        geometry: &Geometry,
+        layout: &ChainLayout,
+        plane: usize,
+        row: usize,
+    ) -> &'a [u16] {
+        let chain_length = layout.chain_length(geometry);
+        let start = plane * layout.words(geometry) + row * chain_length;
+
+        &words[start..start + chain_length]
+    }
+
+    /// Set global brightness, 255 is full brightness.
+    ///
+    /// Scales the time each row stays lit, so the content doesn't have to be redrawn
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness;
+
+        #[cfg(feature = "bcm")]
+        self.update_plane_times(self.planes);
+    }
+
+    pub fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    /// Set gamma correction table, e.g. gamma_2_0(), GAMMA_2_8, linear() or a custom one.
+    ///
+    /// Applies to pixels drawn afterwards
+    pub fn set_gamma(&mut self, table: &[u8; 256]) {
+        self.gamma = *table;
+    }
+
+    /// Set gains of red, green and blue applied after gamma correction, 255 is full intensity.
+    ///
+    /// Applies to pixels drawn afterwards
+    pub fn set_white_balance(&mut self, r: u8, g: u8, b: u8) {
+        self.white_balance = [r, g, b];
+    }
+
+    pub fn white_balance(&self) -> (u8, u8, u8) {
+        (
+            self.white_balance[0],
+            self.white_balance[1],
+            self.white_balance[2],
+        )
+    }
+
+    /// Returns gamma corrected and white balanced r, g, b
+    fn correct(&self, color: Rgb888) -> [u8; 3] {
+        let channel = |value: u8, gain: u8| {
+            (self.gamma[value as usize] as u16 * gain as u16 / 255) as u8
+        };
+
+        [
+            channel(color.r(), self.white_balance[0]),
+            channel(color.g(), self.white_balance[1]),
+            channel(color.b(), self.white_balance[2]),
+        ]
     }
 
     /// Output the buffer to the display
     ///
     /// Takes some time and should be called quite often, otherwise the output
     /// will flicker
+    #[cfg(not(feature = "bcm"))]
     pub fn output<DELAY: DelayUs<u8>>(&mut self, delay: &mut DELAY) {
-        // PWM cycle
-        let brightness = [
-            16, 32, 48, 64, 80, 96, 112, 128, 144, 160, 176, 192, 208, 224, 240, 255,
-        ];
-        for b in brightness.iter() {
-            //brightness = (brightness + 1).saturating_mul(self.brightness_step);
-            self.output_single(delay, *b);
+        let planes = self.scan_planes();
+        let geometry = self.geometry;
+        let layout = self.layout;
+
+        // PWM cycle, every bitplane is shown as many times as its weight
+        for plane in 0..planes {
+            for _ in 0..(1 << plane) {
+                self.output_plane(delay, &geometry, &layout, plane);
+            }
         }
     }
 
-    pub fn output_single<DELAY: DelayUs<u8>>(&mut self, delay: &mut DELAY, brightness: u8) {
-        //hacky, but it's the most efficient way. We need to make sure oe is HIGH when pushing color bits, but only during first iteration.
-        //By assigning it here we don't have to check a condition every iteration of inner loop;
-        let mut address = Self::PINS.oe;
-        let mut output_buffer = 0;
-
-        for (count, row) in self.data.iter().enumerate() {
-            for element in row.iter() {
-                output_buffer = address;
-
-                //Assuming data pins are connected to consecutive pins of a single port starting ftom P0
-                //in this order: r1,g1,b1,r2,g2,b2
-                if element.0 >= brightness {
-                    output_buffer += Self::PINS.r1;
-                }
-                if element.1 >= brightness {
-                    output_buffer += Self::PINS.g1;
-                }
-                if element.2 >= brightness {
-                    output_buffer += Self::PINS.b1;
-                }
-                if element.3 >= brightness {
-                    output_buffer += Self::PINS.r2;
-                }
-                if element.4 >= brightness {
-                    output_buffer += Self::PINS.g2;
-                }
-                if element.5 >= brightness {
-                    output_buffer += Self::PINS.b2;
-                }
-
-                output_buffer += Self::PINS.clock;
-
-                unsafe {
-                    *self.output_port = output_buffer;
-                    output_buffer -= Self::PINS.clock;
-                    *self.output_port = output_buffer;
-                }
-            }
+    /// Shows the given bitplane of every row once
+    #[cfg(not(feature = "bcm"))]
+    pub fn output_single<DELAY: DelayUs<u8>>(&mut self, delay: &mut DELAY, plane: usize) {
+        let planes = self.scan_planes();
+        let geometry = self.geometry;
+        let layout = self.layout;
 
-            output_buffer |= Self::PINS.oe;
-            output_buffer &= !Self::PINS.latch;
+        if plane < planes {
+            self.output_plane(delay, &geometry, &layout, plane);
+        }
+    }
 
-            unsafe {
-                *self.output_port = output_buffer;
+    #[cfg(not(feature = "bcm"))]
+    fn output_plane<DELAY: DelayUs<u8>>(
+        &mut self,
+        delay: &mut DELAY,
+        geometry: &Geometry,
+        layout: &ChainLayout,
+        plane: usize,
+    ) {
+        let brightness = self.brightness;
+        let port = &mut self.output_port;
+
+        //Previous row is lit while the current one is shifted in, so the output is disabled
+        //after this many columns to dim the display
+        let lit_columns = layout.chain_length(geometry) * brightness as usize / 255;
+
+        for count in 0..geometry.scan_rows as usize {
+            let row = Self::chain(&self.words, geometry, layout, plane, count);
+
+            //Last row was shown during the delay at the end of previous pass, so it stays dark
+            //while the first row is shifted in
+            let (lit, dark) = row.split_at(if count == 0 { 0 } else { lit_columns });
+
+            for word in lit.iter() {
+                let word = *word & !Self::PINS.oe;
+                port.write(word | Self::PINS.clock);
+                port.write(word);
+            }
+            for word in dark.iter() {
+                port.write(*word | Self::PINS.clock);
+                port.write(*word);
             }
 
-            output_buffer |= Self::PINS.latch;
+            port.write(Self::PINS.oe);
+            port.write(Self::row_address(count) | Self::PINS.latch);
+        }
 
-            address = 0;
+        //prevents last row from being brighter
+        let last_row_delay = 60 * brightness as u16 / 255;
+        if last_row_delay > 0 {
+            delay.delay_us(last_row_delay as u8);
+        }
 
-            if count & 1 != 0 {
-                address += Self::PINS.a;
-            }
-            if count & 2 != 0 {
-                address += Self::PINS.b;
-            }
-            if count & 4 != 0 {
-                address += Self::PINS.c;
-            }
+        port.write(Self::PINS.oe);
+    }
 
-            output_buffer &= !(Self::PINS.a + Self::PINS.b + Self::PINS.c);
-            output_buffer += address;
-            output_buffer &= !Self::PINS.oe;
+    /// Shows the next bitplane of a row, driven by a timer.
+    ///
+    /// Ends the previously lit bitplane, shifts in the next one with the output disabled
+    /// and lights it. Returns how many us the output has to stay as it is, the application
+    /// calls this again after that time, e.g. from a one-shot timer interrupt. The least
+    /// significant bitplane takes `bcm_unit_us`, every next one twice as long.
+    /// Returns 0 if there is nothing to show
+    #[cfg(feature = "bcm")]
+    pub fn output_step(&mut self) -> u32 {
+        self.output_port.write(Self::PINS.oe);
+
+        //Dimmed bitplane stays dark for the rest of its time
+        if self.scan.dark_us > 0 {
+            return core::mem::replace(&mut self.scan.dark_us, 0);
+        }
 
-            unsafe {
-                *self.output_port = output_buffer;
-            }
+        let planes = self.scan_planes();
+        if planes == 0 {
+            return 0;
         }
+        let geometry = self.geometry;
+        let layout = self.layout;
+        let scan_rows = geometry.scan_rows as usize;
 
-        //prevents last row from being brighter
-        delay.delay_us(60);
+        if self.scan.row >= scan_rows || self.scan.plane >= planes {
+            self.scan.restart();
+        }
+        let row = self.scan.row;
+        let plane = self.scan.plane;
+        self.scan.advance(planes, scan_rows);
+
+        let port = &mut self.output_port;
+        let address = Self::row_address(row);
+        let idle = address | Self::PINS.oe;
+
+        for word in Self::chain(&self.words, &geometry, &layout, plane, row).iter() {
+            port.write(*word | Self::PINS.clock);
+            port.write(*word);
+        }
 
-        output_buffer |= Self::PINS.oe;
-        unsafe {
-            *self.output_port = output_buffer;
+        port.write(idle | Self::PINS.latch);
+        port.write(idle);
+
+        let time = self.plane_times[plane];
+        if time.lit_us == 0 {
+            return time.dark_us;
         }
+
+        port.write(address);
+        self.scan.dark_us = time.dark_us;
+
+        time.lit_us
+    }
+
+    /// Sets how many times per second `output_step` shows every row, 120 by default
+    #[cfg(feature = "bcm")]
+    pub fn set_refresh_rate(&mut self, refresh_rate: u32) {
+        self.refresh_rate = refresh_rate;
+        self.update_plane_times(self.planes);
+    }
+
+    /// Time of the least significant bitplane in `output_step`
+    #[cfg(feature = "bcm")]
+    pub fn bcm_unit_us(&self) -> u32 {
+        let time = self.plane_times[0];
+        time.lit_us + time.dark_us
+    }
+
+    //Splits every bitplane's share of the refresh period into lit and dark time,
+    //so output_step doesn't divide
+    #[cfg(feature = "bcm")]
+    fn update_plane_times(&mut self, planes: usize) {
+        let weights = self.geometry.scan_rows as u32 * ((1 << planes) - 1);
+        let unit_us = (1_000_000 / (self.refresh_rate * weights).max(1)).max(1);
+
+        for (plane, time) in self.plane_times.iter_mut().enumerate() {
+            let plane_us = unit_us << plane;
+            let lit_us = plane_us * self.brightness as u32 / 255;
+
+            *time = scan::PlaneTime {
+                lit_us,
+                dark_us: plane_us - lit_us,
+            };
+        }
+    }
+
+    /// Disables the output until the next `output_step`, e.g. when scan-out is paused
+    #[cfg(feature = "bcm")]
+    pub fn blank(&mut self) {
+        self.output_port.write(Self::PINS.oe);
     }
 
     /// Clear the output
@@ -233,87 +561,156 @@ impl<const PIN_POS: Pins, const ROW_LENGTH: usize> Hub75<PIN_POS, ROW_LENGTH> {
     /// It's a bit faster than using the embedded_graphics interface
     /// to do the same
     pub fn clear_display(&mut self) {
-        for row in self.data.iter_mut() {
-            for e in row.iter_mut() {
-                e.0 = 0;
-                e.1 = 0;
-                e.2 = 0;
-                e.3 = 0;
-                e.4 = 0;
-                e.5 = 0;
-            }
+        self.fill(0, self.planes);
+    }
+
+    /// Sets every pixel of the given bitplanes to the given color word
+    fn fill(&mut self, colors: u16, planes: usize) {
+        let chain_length = self.layout.chain_length(&self.geometry);
+        let scan_rows = self.geometry.scan_rows as usize;
+        let used = planes * self.layout.words(&self.geometry);
+
+        for (index, row) in self.words[..used].chunks_mut(chain_length).enumerate() {
+            row.fill(colors | Self::shift_control(index % scan_rows, scan_rows));
         }
     }
 
-    #[cfg(not(feature = "stripe-multiplexing"))]
     fn draw_pixel(
         &mut self,
         item: Pixel<Rgb888>,
-    ) -> Result<(), <Hub75<PIN_POS, ROW_LENGTH> as DrawTarget>::Error> {
+    ) -> Result<(), <Self as DrawTarget>::Error> {
         let Pixel(coord, color) = item;
 
-        let column = coord[0];
-        let row = coord[1];
+        if let Some((row, column, lower_half)) = self.locate(coord[0], coord[1]) {
+            self.set_pixel(row, column, lower_half, color);
+        }
+
+        Ok(())
+    }
 
-        if column < 0 || column >= ROW_LENGTH as i32 || row < 0 || row >= (NUM_ROWS * 2) as i32 {
-            return Ok(());
+    /// Returns scan line, position in the chain and half of the panel
+    /// of a pixel in the drawing area, None outside of it
+    fn locate(&self, x: i32, y: i32) -> Option<(usize, usize, bool)> {
+        let canvas = self.layout.size(&self.geometry);
+        let (width, height) = self.orientation.size(canvas);
+
+        if x < 0 || x >= width as i32 || y < 0 || y >= height as i32 {
+            return None;
         }
 
-        let mut pixel_tuple = &mut self.data[row as usize % NUM_ROWS][column as usize];
+        let (x, y) = self.orientation.to_canvas(canvas, x as usize, y as usize);
 
-        if row > 15 {
-            pixel_tuple.3 = GAMMA8[color.r() as usize];
-            pixel_tuple.4 = GAMMA8[color.g() as usize];
-            pixel_tuple.5 = GAMMA8[color.b() as usize];
-        } else {
-            pixel_tuple.0 = GAMMA8[color.r() as usize];
-            pixel_tuple.1 = GAMMA8[color.g() as usize];
-            pixel_tuple.2 = GAMMA8[color.b() as usize];
+        Some(self.layout.map(&self.geometry, x, y))
+    }
+
+    /// Stored r, g, b of a pixel with color_depth bits each,
+    /// after gamma and white balance correction
+    pub fn pixel(&self, x: i32, y: i32) -> Option<[u8; 3]> {
+        let (row, column, lower_half) = self.locate(x, y)?;
+        let shift = if lower_half { 3 } else { 0 };
+
+        let plane_words = self.layout.words(&self.geometry);
+        let index = row * self.layout.chain_length(&self.geometry) + column;
+
+        let mut color = [0; 3];
+        for plane in 0..self.planes {
+            let bits = self.color_bits(plane * plane_words + index) >> shift;
+
+            for (channel, value) in color.iter_mut().enumerate() {
+                *value |= ((bits >> channel) & 1) << plane;
+            }
         }
 
-        Ok(())
+        Some(color)
     }
 
-    #[cfg(feature = "stripe-multiplexing")]
-    fn draw_pixel(
-        &mut self,
-        item: Pixel<Rgb888>,
-    ) -> Result<(), <Hub75<PIN_POS, ROW_LENGTH> as DrawTarget>::Error> {
-        let Pixel(coord, color) = item;
+    /// Color of a pixel as drawn, recovered from the stored bits by inverting the current
+    /// gamma correction and white balance. Bits below the color depth are lost
+    pub fn pixel_color(&self, x: i32, y: i32) -> Option<Rgb888> {
+        let stored = self.pixel(x, y)?;
+        let channel = |c: usize| self.uncorrect(stored[c], self.white_balance[c]);
+
+        Some(Rgb888::new(channel(0), channel(1), channel(2)))
+    }
+
+    /// Smallest drawn value of a channel which is stored as the given one
+    fn uncorrect(&self, stored: u8, gain: u8) -> u8 {
+        let shift = 8 - self.planes;
 
-        let mut x = coord[0] as usize;
-        let mut y = coord[1] as usize;
+        (0..=255)
+            .find(|value| {
+                let corrected = self.gamma[*value as usize] as u16 * gain as u16 / 255;
+                (corrected >> shift) as u8 >= stored
+            })
+            .unwrap_or(255)
+    }
+
+    /// Stores gamma corrected color of a pixel in the upper or lower half of its panel
+    fn set_pixel(&mut self, row: usize, column: usize, lower_half: bool, color: Rgb888) {
+        let (mask, shift) = if lower_half {
+            (Self::PINS.r2 | Self::PINS.g2 | Self::PINS.b2, 3)
+        } else {
+            (Self::PINS.r1 | Self::PINS.g1 | Self::PINS.b1, 0)
+        };
 
-        if (x < 0 || x >= ROW_LENGTH / 2 || y < 0 || y >= NUM_ROWS * 2) {
-            return Ok(());
+        let color = self.correct(color);
+        let plane_words = self.layout.words(&self.geometry);
+        let index = row * self.layout.chain_length(&self.geometry) + column;
+
+        for plane in 0..self.planes {
+            let bits = self.plane_bits(color, plane);
+            let word = &mut self.words[plane * plane_words + index];
+            *word = (*word & !mask) | Self::color_word(bits << shift);
         }
+    }
 
-        let is_top_stripe = (y % NUM_ROWS) < NUM_ROWS / 2;
+    /// Returns bits of corrected r, g, b shown in the given bitplane
+    fn plane_bits(&self, color: [u8; 3], plane: usize) -> u8 {
+        let bit = plane + 8 - self.planes;
+        let r = (color[0] >> bit) & 1;
+        let g = (color[1] >> bit) & 1;
+        let b = (color[2] >> bit) & 1;
 
-        let screen_offset = x / 32;
+        r | (g << 1) | (b << 2)
+    }
 
-        x = x + (screen_offset * 32);
+    /// Number of stored words with colors of all bitplanes, see color_bits
+    pub fn color_words(&self) -> usize {
+        self.planes * self.layout.words(&self.geometry)
+    }
 
-        if is_top_stripe {
-            x = x + 32;
+    /// Raw r1, g1, b1, r2, g2, b2 bits of a stored word packed into bits 0-5,
+    /// for saving the framebuffer with the current geometry and color depth
+    pub fn color_bits(&self, index: usize) -> u8 {
+        let word = self.words[index];
+
+        let mut colors = 0;
+        for (bit, pin) in Self::COLOR_PINS.iter().enumerate() {
+            if word & pin != 0 {
+                colors |= 1 << bit;
+            }
         }
 
-        let column = x;
-        let row = y % (NUM_ROWS / 2);
+        colors
+    }
 
-        let mut pixel_tuple = &mut self.data[row as usize][column as usize];
+    /// Restores bits returned by color_bits
+    pub fn set_color_bits(&mut self, index: usize, colors: u8) {
+        let mask = Self::color_word(0x3F);
+        let word = &mut self.words[index];
+        *word = (*word & !mask) | Self::color_word(colors);
+    }
 
-        if y > 15 {
-            pixel_tuple.3 = GAMMA8[color.r() as usize];
-            pixel_tuple.4 = GAMMA8[color.g() as usize];
-            pixel_tuple.5 = GAMMA8[color.b() as usize];
-        } else {
-            pixel_tuple.0 = GAMMA8[color.r() as usize];
-            pixel_tuple.1 = GAMMA8[color.g() as usize];
-            pixel_tuple.2 = GAMMA8[color.b() as usize];
+    /// Converts r1, g1, b1, r2, g2, b2 packed into bits 0-5 to the port word
+    fn color_word(colors: u8) -> u16 {
+        let mut word = 0;
+        for (bit, pin) in Self::COLOR_PINS.iter().enumerate() {
+            if colors & (1 << bit) != 0 {
+                word |= pin;
+            }
         }
 
-        Ok(())
+        word
     }
 }
 
@@ -324,7 +721,9 @@ use embedded_graphics::{
     Pixel,
 };
 
-impl<const PIN_POS: Pins, const ROW_LENGTH: usize> DrawTarget for Hub75<PIN_POS, ROW_LENGTH> {
+impl<PORT: OutputPort, const PIN_POS: Pins, const WORDS: usize> DrawTarget
+    for Hub75<PORT, PIN_POS, WORDS>
+{
     type Error = core::convert::Infallible;
     type Color = Rgb888;
 
@@ -342,20 +741,19 @@ impl<const PIN_POS: Pins, const ROW_LENGTH: usize> DrawTarget for Hub75<PIN_POS,
     }
 
     fn clear(&mut self, color: Rgb888) -> Result<(), Self::Error> {
-        #[cfg(not(feature = "stripe-multiplexing"))]
-        let rows = NUM_ROWS;
-        #[cfg(feature = "stripe-multiplexing")]
-        let rows = NUM_ROWS / 2;
-
-        for row in 0..rows {
-            for column in 0..ROW_LENGTH {
-                let pixel_tuple = &mut self.data[row][column];
-                pixel_tuple.0 = GAMMA8[color.r() as usize];
-                pixel_tuple.1 = GAMMA8[color.g() as usize];
-                pixel_tuple.2 = GAMMA8[color.b() as usize];
-                pixel_tuple.3 = GAMMA8[color.r() as usize];
-                pixel_tuple.4 = GAMMA8[color.g() as usize];
-                pixel_tuple.5 = GAMMA8[color.b() as usize];
+        let color = self.correct(color);
+        let plane_words = self.layout.words(&self.geometry);
+        let chain_length = self.layout.chain_length(&self.geometry);
+        let scan_rows = self.geometry.scan_rows as usize;
+
+        for plane in 0..self.planes {
+            let bits = self.plane_bits(color, plane);
+            let colors = Self::color_word(bits | (bits << 3));
+
+            let start = plane * plane_words;
+            let rows = self.words[start..start + plane_words].chunks_mut(chain_length);
+            for (count, row) in rows.enumerate() {
+                row.fill(colors | Self::shift_control(count, scan_rows));
             }
         }
 
@@ -363,14 +761,16 @@ impl<const PIN_POS: Pins, const ROW_LENGTH: usize> DrawTarget for Hub75<PIN_POS,
     }
 }
 
-impl<const PIN_POS: Pins, const ROW_LENGTH: usize> OriginDimensions for Hub75<PIN_POS, ROW_LENGTH> {
+impl<PORT: OutputPort, const PIN_POS: Pins, const WORDS: usize> OriginDimensions
+    for Hub75<PORT, PIN_POS, WORDS>
+{
     fn size(&self) -> Size {
+        let canvas = self.layout.size(&self.geometry);
+        let (width, height) = self.orientation.size(canvas);
+
         Size {
-            #[cfg(not(feature = "stripe-multiplexing"))]
-            width: ROW_LENGTH as u32,
-            #[cfg(feature = "stripe-multiplexing")]
-            width: (ROW_LENGTH as u32) / 2,
-            height: (NUM_ROWS * 2) as u32,
+            width: width as u32,
+            height: height as u32,
         }
     }
 }
@@ -0,0 +1,546 @@
+//! Host-side recorder of the scan-out waveform and a model of the panels decoding it.
+//!
+//! Run the tests with `cargo test -p hub75 --target x86_64-unknown-linux-gnu`,
+//! the workspace builds for the MCU by default.
+
+extern crate std;
+
+use std::{cell::RefCell, collections::VecDeque, rc::Rc, vec, vec::Vec};
+
+#[cfg(not(feature = "bcm"))]
+use embedded_hal::blocking::delay::DelayUs;
+
+use super::{OutputPort, Pins};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    Write(u16),
+    DelayUs(u32),
+}
+
+/// Port and delay sharing one log of everything the driver did
+#[derive(Clone, Default)]
+pub struct MockPort {
+    events: Rc<RefCell<Vec<Event>>>,
+}
+
+impl MockPort {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delay logging into the same record as the port
+    #[cfg(not(feature = "bcm"))]
+    pub fn delay(&self) -> MockDelay {
+        MockDelay {
+            events: self.events.clone(),
+        }
+    }
+
+    /// Records the time until the timer calls the driver again
+    #[cfg(feature = "bcm")]
+    pub fn wait(&self, us: u32) {
+        self.events.borrow_mut().push(Event::DelayUs(us));
+    }
+
+    /// Returns recorded events and starts a new record
+    pub fn take(&self) -> Vec<Event> {
+        self.events.replace(Vec::new())
+    }
+}
+
+impl OutputPort for MockPort {
+    fn write(&mut self, word: u16) {
+        self.events.borrow_mut().push(Event::Write(word));
+    }
+}
+
+#[cfg(not(feature = "bcm"))]
+pub struct MockDelay {
+    events: Rc<RefCell<Vec<Event>>>,
+}
+
+#[cfg(not(feature = "bcm"))]
+impl DelayUs<u8> for MockDelay {
+    fn delay_us(&mut self, us: u8) {
+        self.events.borrow_mut().push(Event::DelayUs(us as u32));
+    }
+}
+
+/// Contents of the output registers of all panels after a latch
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatchedRow {
+    /// Scan line selected by A-E at the time of the latch
+    pub address: usize,
+    /// r1, g1, b1, r2, g2, b2 packed into bits 0-5, indexed by order of shifting in,
+    /// 0 is at the far end of the chain
+    pub colors: Vec<u8>,
+    /// Clock pulses since the previous latch
+    pub clocks: usize,
+}
+
+/// Behaves like a chain of HUB75 panels: shifts colors in on rising clock,
+/// copies them to the outputs on rising latch and lights the addressed row while OE is low
+pub struct Decoder {
+    pins: Pins,
+    chain_length: usize,
+    scan_rows: usize,
+
+    shift_register: VecDeque<u8>,
+    clocks: usize,
+    previous: u16,
+
+    pub latched: Vec<LatchedRow>,
+    //Time in us every r1, g1, b1, r2, g2, b2 output was lit, indexed by scan line and position
+    lit_time: Vec<Vec<[u32; 6]>>,
+}
+
+impl Decoder {
+    /// pins are bit positions of the signals as in PIN_POS
+    pub fn new(pins: Pins, chain_length: usize, scan_rows: usize) -> Self {
+        //Idle with output disabled
+        let previous = 1 << pins.oe;
+
+        Decoder {
+            pins,
+            chain_length,
+            scan_rows,
+            shift_register: VecDeque::new(),
+            clocks: 0,
+            previous,
+            latched: Vec::new(),
+            lit_time: vec![vec![[0; 6]; chain_length]; scan_rows],
+        }
+    }
+
+    pub fn feed(&mut self, events: &[Event]) {
+        for event in events {
+            match *event {
+                Event::Write(word) => self.write(word),
+                Event::DelayUs(us) => self.light(us),
+            }
+        }
+    }
+
+    /// Last word written to the port
+    pub fn port(&self) -> u16 {
+        self.previous
+    }
+
+    /// How many latched rows had the output set, r1, g1, b1 are channels 0-2, r2, g2, b2 3-5.
+    ///
+    /// Corresponds to intensity with PWM, where every bitplane is latched as many times
+    /// as its weight
+    #[cfg_attr(feature = "bcm", allow(dead_code))]
+    pub fn latch_count(&self, address: usize, position: usize, channel: usize) -> u32 {
+        self.latched
+            .iter()
+            .filter(|row| row.address == address && row.colors[position] & (1 << channel) != 0)
+            .count() as u32
+    }
+
+    /// How long the output was lit during delays, corresponds to intensity with BCM
+    #[cfg_attr(not(feature = "bcm"), allow(dead_code))]
+    pub fn lit_time(&self, address: usize, position: usize, channel: usize) -> u32 {
+        self.lit_time[address][position][channel]
+    }
+
+    fn bit(word: u16, pin: u16) -> bool {
+        word & (1 << pin) != 0
+    }
+
+    fn rising(&self, word: u16, pin: u16) -> bool {
+        !Self::bit(self.previous, pin) && Self::bit(word, pin)
+    }
+
+    fn address(&self, word: u16) -> usize {
+        let lines = [
+            self.pins.a,
+            self.pins.b,
+            self.pins.c,
+            self.pins.d,
+            self.pins.e,
+        ];
+
+        let address: usize = lines
+            .iter()
+            .enumerate()
+            .filter(|(_, pin)| Self::bit(word, **pin))
+            .map(|(bit, _)| 1 << bit)
+            .sum();
+
+        address % self.scan_rows
+    }
+
+    fn write(&mut self, word: u16) {
+        if self.rising(word, self.pins.clock) {
+            let data = [
+                self.pins.r1,
+                self.pins.g1,
+                self.pins.b1,
+                self.pins.r2,
+                self.pins.g2,
+                self.pins.b2,
+            ];
+            let colors: u8 = data
+                .iter()
+                .enumerate()
+                .filter(|(_, pin)| Self::bit(word, **pin))
+                .map(|(bit, _)| 1 << bit)
+                .sum();
+
+            //Pixel shifted in first travels to the far end of the chain
+            self.shift_register.push_front(colors);
+            self.shift_register.truncate(self.chain_length);
+            self.clocks += 1;
+        }
+
+        if self.rising(word, self.pins.latch) {
+            let mut colors: Vec<u8> = self.shift_register.iter().rev().copied().collect();
+            colors.resize(self.chain_length, 0);
+
+            self.latched.push(LatchedRow {
+                address: self.address(word),
+                colors,
+                clocks: self.clocks,
+            });
+            self.clocks = 0;
+        }
+
+        self.previous = word;
+    }
+
+    fn light(&mut self, us: u32) {
+        if Self::bit(self.previous, self.pins.oe) {
+            return;
+        }
+
+        let address = self.address(self.previous);
+        let row = match self.latched.last() {
+            Some(row) => row,
+            None => return,
+        };
+        let lit = &mut self.lit_time[address];
+
+        for (colors, lit) in row.colors.iter().zip(lit.iter_mut()) {
+            for (channel, time) in lit.iter_mut().enumerate() {
+                if colors & (1 << channel) != 0 {
+                    *time += us;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{linear, PIN_PORT_POS};
+    use crate::{ChainDirection, ChainLayout, Geometry, Hub75, Mapping, Orientation, Rotation};
+
+    use embedded_graphics::{pixelcolor::Rgb888, prelude::*, Pixel};
+
+    type TestHub75 = Hub75<MockPort, PIN_PORT_POS, 4096>;
+
+    const STRIPE_64X32: Geometry = Geometry::new(64, 32, 8, Mapping::Stripe(32));
+
+    fn hub75(geometry: Geometry) -> (TestHub75, MockPort) {
+        let port = MockPort::new();
+        let mut hub75 = Hub75::new(port.clone(), geometry).unwrap();
+        //Top bits of drawn colors are shown unchanged
+        hub75.set_gamma(&linear());
+
+        (hub75, port)
+    }
+
+    /// Runs one full scan-out and feeds the waveform into a model of the panels
+    fn scan(hub75: &mut TestHub75, port: &MockPort) -> Decoder {
+        let layout = hub75.layout();
+        let geometry = hub75.geometry();
+        let mut decoder = Decoder::new(
+            PIN_PORT_POS,
+            layout.chain_length(&geometry),
+            geometry.scan_rows as usize,
+        );
+
+        port.take();
+        #[cfg(not(feature = "bcm"))]
+        hub75.output(&mut port.delay());
+        #[cfg(feature = "bcm")]
+        {
+            //At full brightness every step lights a whole bitplane,
+            //recorded in units of the least significant one
+            let unit_us = hub75.bcm_unit_us();
+            for _ in 0..geometry.scan_rows as usize * hub75.color_depth() {
+                let us = hub75.output_step();
+                port.wait(us / unit_us);
+            }
+            hub75.blank();
+        }
+        decoder.feed(&port.take());
+
+        decoder
+    }
+
+    /// Intensity of the output in bitplane units, every bitplane is latched
+    /// as many times as its weight
+    #[cfg(not(feature = "bcm"))]
+    fn intensity(decoder: &Decoder, address: usize, position: usize, channel: usize) -> u32 {
+        decoder.latch_count(address, position, channel)
+    }
+
+    /// Intensity of the output in bitplane units, every bitplane is lit
+    /// for time proportional to its weight, see scan
+    #[cfg(feature = "bcm")]
+    fn intensity(decoder: &Decoder, address: usize, position: usize, channel: usize) -> u32 {
+        decoder.lit_time(address, position, channel)
+    }
+
+    /// Color shown at given output as drawn, only the bits fitting into the color depth
+    fn shown_color(
+        decoder: &Decoder,
+        depth: usize,
+        address: usize,
+        position: usize,
+        lower_half: bool,
+    ) -> Rgb888 {
+        let first = if lower_half { 3 } else { 0 };
+        let channel =
+            |c: usize| (intensity(decoder, address, position, first + c) << (8 - depth)) as u8;
+
+        Rgb888::new(channel(0), channel(1), channel(2))
+    }
+
+    /// Color with only the bits fitting into the color depth
+    fn truncate(color: Rgb888, depth: usize) -> Rgb888 {
+        let mask = !((1u16 << (8 - depth)) - 1) as u8;
+        Rgb888::new(color.r() & mask, color.g() & mask, color.b() & mask)
+    }
+
+    /// Distinct color for every pixel of the canvas
+    fn test_color(x: i32, y: i32) -> Rgb888 {
+        let i = (y * 64 + x) as u32 * 7;
+        Rgb888::new((i << 4) as u8, (i & 0xF0) as u8, ((i >> 4) & 0xF0) as u8)
+    }
+
+    /// Where a 64x32 1/8 scan panel lights the output: the chain runs through 32 pixel
+    /// wide segments of the lower and then the upper 8 rows of each half
+    fn stripe_pixel(address: usize, position: usize, lower_half: bool) -> (i32, i32) {
+        let segment = position / 64;
+        let upper_block = position % 64 >= 32;
+
+        let x = segment * 32 + position % 32;
+        let y = address + if upper_block { 0 } else { 8 } + if lower_half { 16 } else { 0 };
+
+        (x as i32, y as i32)
+    }
+
+    #[test]
+    fn every_latch_follows_a_full_chain() {
+        let (mut hub75, port) = hub75(STRIPE_64X32);
+        let decoder = scan(&mut hub75, &port);
+
+        //BCM latches all bitplanes of a row before moving to the next one
+        let repeats = if cfg!(feature = "bcm") {
+            hub75.color_depth()
+        } else {
+            1
+        };
+
+        assert!(!decoder.latched.is_empty());
+        for (i, row) in decoder.latched.iter().enumerate() {
+            assert_eq!(row.clocks, 128);
+            assert_eq!(row.address, i / repeats % 8);
+        }
+
+        //Output is left disabled
+        assert_ne!(decoder.port() & (1 << PIN_PORT_POS.oe), 0);
+    }
+
+    #[test]
+    fn stripe_multiplexing_shows_the_drawn_image() {
+        let (mut hub75, port) = hub75(STRIPE_64X32);
+        let depth = hub75.color_depth();
+
+        let pixels =
+            (0..32).flat_map(|y| (0..64).map(move |x| Pixel(Point::new(x, y), test_color(x, y))));
+        hub75.draw_iter(pixels).unwrap();
+        let decoder = scan(&mut hub75, &port);
+
+        for address in 0..8 {
+            for position in 0..128 {
+                for &lower_half in [false, true].iter() {
+                    let (x, y) = stripe_pixel(address, position, lower_half);
+                    assert_eq!(
+                        shown_color(&decoder, depth, address, position, lower_half),
+                        truncate(test_color(x, y), depth),
+                        "pixel {}, {}",
+                        x,
+                        y
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn direct_mapping_shows_the_drawn_image() {
+        let (mut hub75, port) = hub75(Geometry::new(32, 16, 8, Mapping::Direct));
+        let depth = hub75.color_depth();
+
+        let pixels =
+            (0..16).flat_map(|y| (0..32).map(move |x| Pixel(Point::new(x, y), test_color(x, y))));
+        hub75.draw_iter(pixels).unwrap();
+        let decoder = scan(&mut hub75, &port);
+
+        for address in 0..8 {
+            for x in 0..32 {
+                assert_eq!(
+                    shown_color(&decoder, depth, address, x, false),
+                    truncate(test_color(x as i32, address as i32), depth)
+                );
+                assert_eq!(
+                    shown_color(&decoder, depth, address, x, true),
+                    truncate(test_color(x as i32, address as i32 + 8), depth)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn chained_panels_start_at_the_controller() {
+        let (mut hub75, port) = hub75(STRIPE_64X32);
+        hub75
+            .set_layout(ChainLayout::new(2, 1, ChainDirection::Horizontal, false))
+            .unwrap();
+
+        //Left panel is connected to the controller, so its data is shifted in last
+        let white = Rgb888::new(255, 255, 255);
+        Pixel(Point::new(5, 3), white).draw(&mut hub75).unwrap();
+        let decoder = scan(&mut hub75, &port);
+
+        assert_eq!(stripe_pixel(3, 37, false), (5, 3));
+        assert_ne!(intensity(&decoder, 3, 128 + 37, 0), 0);
+        assert_eq!(intensity(&decoder, 3, 37, 0), 0);
+    }
+
+    #[test]
+    fn orientation_transforms_drawn_pixels() {
+        let (mut hub75, port) = hub75(STRIPE_64X32);
+        hub75.set_orientation(Orientation::new(Rotation::Rotate180, false, false));
+
+        let white = Rgb888::new(255, 255, 255);
+        Pixel(Point::new(0, 0), white).draw(&mut hub75).unwrap();
+        let decoder = scan(&mut hub75, &port);
+
+        //Bottom right corner in the last segment of the upper block of the lower half
+        assert_eq!(stripe_pixel(7, 95, true), (63, 31));
+        assert_ne!(intensity(&decoder, 7, 95, 3), 0);
+    }
+
+    #[test]
+    fn stored_pixels_read_back_as_drawn() {
+        let (mut hub75, _port) = hub75(STRIPE_64X32);
+        hub75.set_orientation(Orientation::new(Rotation::Rotate90, true, false));
+        let depth = hub75.color_depth();
+
+        let size = hub75.size();
+        for y in 0..size.height as i32 {
+            for x in 0..size.width as i32 {
+                Pixel(Point::new(x, y), test_color(x, y))
+                    .draw(&mut hub75)
+                    .unwrap();
+            }
+        }
+
+        for y in 0..size.height as i32 {
+            for x in 0..size.width as i32 {
+                let [r, g, b] = hub75.pixel(x, y).unwrap();
+                let stored = Rgb888::new(r << (8 - depth), g << (8 - depth), b << (8 - depth));
+                assert_eq!(stored, truncate(test_color(x, y), depth), "at {} {}", x, y);
+            }
+        }
+
+        assert_eq!(hub75.pixel(size.width as i32, 0), None);
+    }
+
+    #[test]
+    fn pixel_color_reverts_correction() {
+        let (mut hub75, _port) = hub75(STRIPE_64X32);
+        let depth = hub75.color_depth();
+
+        Pixel(Point::new(3, 4), test_color(3, 4))
+            .draw(&mut hub75)
+            .unwrap();
+        assert_eq!(
+            hub75.pixel_color(3, 4),
+            Some(truncate(test_color(3, 4), depth))
+        );
+
+        hub75.set_gamma(&crate::GAMMA_2_8);
+        hub75.set_white_balance(255, 200, 128);
+
+        for x in 0..64 {
+            let color = test_color(x, 0);
+            Pixel(Point::new(x, 0), color).draw(&mut hub75).unwrap();
+            let stored = hub75.pixel(x, 0);
+
+            //Drawing the recovered color again stores the same bits
+            let recovered = hub75.pixel_color(x, 0).unwrap();
+            Pixel(Point::new(x, 0), recovered).draw(&mut hub75).unwrap();
+            assert_eq!(hub75.pixel(x, 0), stored, "at {}", x);
+        }
+    }
+
+    #[cfg(not(feature = "bcm"))]
+    #[test]
+    fn bitplane_beyond_color_depth_is_skipped() {
+        let (mut hub75, port) = hub75(STRIPE_64X32);
+        let plane = hub75.color_depth() - 1;
+
+        //Fewer bitplanes fit after the change
+        hub75
+            .set_geometry(Geometry::new(64, 64, 32, Mapping::Direct))
+            .unwrap();
+        assert!(hub75.color_depth() <= plane);
+
+        port.take();
+        hub75.output_single(&mut port.delay(), plane);
+        assert!(port.take().is_empty());
+    }
+
+    #[cfg(feature = "bcm")]
+    #[test]
+    fn dimmed_bitplanes_keep_their_time() {
+        let (mut hub75, _port) = hub75(STRIPE_64X32);
+        hub75.set_brightness(128);
+
+        //Output is disabled for the rest of the bitplane's time by an extra step
+        let unit_us = hub75.bcm_unit_us();
+        for plane in 0..hub75.color_depth() {
+            let lit = hub75.output_step();
+            let dark = hub75.output_step();
+
+            assert_eq!(lit, (unit_us << plane) * 128 / 255);
+            assert_eq!(lit + dark, unit_us << plane);
+        }
+    }
+
+    #[cfg(feature = "bcm")]
+    #[test]
+    fn bitplane_times_follow_settings() {
+        let (mut hub75, _port) = hub75(STRIPE_64X32);
+        hub75.set_refresh_rate(100);
+
+        //Every scan line shows all bitplanes 100 times per second
+        let weights = |hub75: &TestHub75| 8 * ((1 << hub75.color_depth()) - 1);
+        assert_eq!(hub75.bcm_unit_us(), 1_000_000 / (100 * weights(&hub75)));
+
+        //Smaller panel has room for more bitplanes, each of them gets less time
+        hub75
+            .set_geometry(Geometry::new(32, 16, 8, Mapping::Direct))
+            .unwrap();
+        assert_eq!(hub75.color_depth(), 6);
+        assert_eq!(hub75.bcm_unit_us(), 1_000_000 / (100 * weights(&hub75)));
+    }
+}
@@ -0,0 +1,51 @@
+use super::Rotation;
+
+/// How the whole canvas is viewed, applied to coordinates of every drawn pixel.
+///
+/// The image is rotated clockwise first and then mirrored
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Orientation {
+    pub rotation: Rotation,
+    /// Mirrors left and right
+    pub flip_horizontal: bool,
+    /// Mirrors top and bottom
+    pub flip_vertical: bool,
+}
+
+impl Orientation {
+    pub const fn new(rotation: Rotation, flip_horizontal: bool, flip_vertical: bool) -> Self {
+        Orientation {
+            rotation,
+            flip_horizontal,
+            flip_vertical,
+        }
+    }
+
+    pub const fn normal() -> Self {
+        Self::new(Rotation::Normal, false, false)
+    }
+
+    /// Size of the drawing area on a canvas of given size
+    pub fn size(&self, canvas: (u16, u16)) -> (u16, u16) {
+        self.rotation.rotate_size(canvas.0, canvas.1)
+    }
+
+    /// Converts coordinates of the drawing area to the coordinates of the canvas
+    pub fn to_canvas(&self, canvas: (u16, u16), x: usize, y: usize) -> (usize, usize) {
+        let (width, height) = self.size(canvas);
+
+        let x = if self.flip_horizontal {
+            width as usize - 1 - x
+        } else {
+            x
+        };
+        let y = if self.flip_vertical {
+            height as usize - 1 - y
+        } else {
+            y
+        };
+
+        self.rotation
+            .unrotate(canvas.0 as usize, canvas.1 as usize, x, y)
+    }
+}
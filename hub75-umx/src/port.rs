@@ -0,0 +1,102 @@
+use core::ptr::write_volatile;
+
+use embedded_hal::digital::v2::OutputPin;
+
+use super::Pins;
+
+/// Sink for port words, bits of every signal are placed as given by PIN_POS of Hub75
+pub trait OutputPort {
+    fn write(&mut self, word: u16);
+}
+
+/// Output data register of a 16 bit GPIO port with all signals connected to it
+pub struct RawPort {
+    register: *mut u16,
+}
+
+impl RawPort {
+    /// # Safety
+    /// register has to be a valid output data register not written by anything else
+    pub unsafe fn new(register: *mut u16) -> Self {
+        RawPort { register }
+    }
+
+    pub fn address(&self) -> *mut u16 {
+        self.register
+    }
+}
+
+impl OutputPort for RawPort {
+    #[inline(always)]
+    fn write(&mut self, word: u16) {
+        unsafe {
+            write_volatile(self.register, word);
+        }
+    }
+}
+
+/// Bit positions of the signals in words written to PinPort
+pub const PIN_PORT_POS: Pins = Pins {
+    r1: 0,
+    g1: 1,
+    b1: 2,
+    r2: 3,
+    g2: 4,
+    b2: 5,
+    a: 6,
+    b: 7,
+    c: 8,
+    d: 9,
+    e: 10,
+    clock: 11,
+    latch: 12,
+    oe: 13,
+};
+
+/// Signals on separate pins, e.g. split across ports or on other MCUs.
+///
+/// Has to be used with PIN_PORT_POS. Only pins whose level changed are written,
+/// in the order of PIN_PORT_POS, so colors and address settle before clock and latch rise.
+/// The output is considerably slower than with RawPort
+pub struct PinPort<P: OutputPin> {
+    //r1, g1, b1, r2, g2, b2, A-E, clock, latch, OE
+    pins: [P; 14],
+    last: u16,
+}
+
+impl<P: OutputPin> PinPort<P> {
+    pub fn new(mut pins: [P; 14]) -> Self {
+        for pin in pins.iter_mut() {
+            pin.set_low().ok();
+        }
+
+        //Output stays disabled until the first row is shifted in
+        let mut port = PinPort { pins, last: 0 };
+        port.write(1 << PIN_PORT_POS.oe);
+        port
+    }
+
+    pub fn release(self) -> [P; 14] {
+        self.pins
+    }
+}
+
+impl<P: OutputPin> OutputPort for PinPort<P> {
+    fn write(&mut self, word: u16) {
+        let changed = word ^ self.last;
+
+        for (bit, pin) in self.pins.iter_mut().enumerate() {
+            if changed & (1 << bit) == 0 {
+                continue;
+            }
+
+            if word & (1 << bit) != 0 {
+                pin.set_high().ok();
+            } else {
+                pin.set_low().ok();
+            }
+        }
+
+        self.last = word;
+    }
+}
@@ -0,0 +1,46 @@
+/// Position of a scan-out which shows one bitplane of a row at a time,
+/// bitplane shifted in or currently lit
+pub struct ScanState {
+    pub row: usize,
+    pub plane: usize,
+    //Rest of the current bitplane's time the output stays disabled to dim the display
+    #[cfg(feature = "bcm")]
+    pub dark_us: u32,
+    #[cfg(feature = "stm32f1-dma")]
+    pub running: bool,
+}
+
+impl ScanState {
+    pub const fn new() -> Self {
+        ScanState {
+            row: 0,
+            plane: 0,
+            #[cfg(feature = "bcm")]
+            dark_us: 0,
+            #[cfg(feature = "stm32f1-dma")]
+            running: false,
+        }
+    }
+
+    /// Continues from the first row, e.g. after geometry change
+    pub fn restart(&mut self) {
+        self.row = 0;
+        self.plane = 0;
+    }
+
+    pub fn advance(&mut self, planes: usize, scan_rows: usize) {
+        self.plane += 1;
+        if self.plane >= planes {
+            self.plane = 0;
+            self.row = (self.row + 1) % scan_rows;
+        }
+    }
+}
+
+/// How long a bitplane is lit and then kept dark to dim it
+#[cfg(feature = "bcm")]
+#[derive(Clone, Copy, Default)]
+pub struct PlaneTime {
+    pub lit_us: u32,
+    pub dark_us: u32,
+}
@@ -1,6 +1,8 @@
 /* Linker script for the STM32F103C8T6 */
 MEMORY
 {
-  FLASH : ORIGIN = 0x08000000, LENGTH = 64K
+  /* Last 5K of the 64K flash are reserved for the configuration (1K)
+     and the state saved by SaveState (4K) */
+  FLASH : ORIGIN = 0x08000000, LENGTH = 59K
   RAM : ORIGIN = 0x20000000, LENGTH = 20K
 }
\ No newline at end of file
@@ -0,0 +1,138 @@
+use core::{
+    fmt::Write as _,
+    sync::atomic::{AtomicU16, Ordering},
+};
+
+use embedded_graphics::{draw_target::DrawTarget, pixelcolor::Rgb888};
+use heapless::String;
+
+use crate::{
+    config,
+    display::{
+        panel::Panel,
+        text_animations::{BlinkingAnimation, SlideAnimation, SlideDirection, TextAnimation},
+        DisplayError, DisplayMode, TextDisplay,
+    },
+    saved_state,
+};
+
+//Ticks left until the boot screen replaces device info, stops at 1 until taken
+static INFO_TICKS: AtomicU16 = AtomicU16::new(0);
+
+/// Stores how long device info is shown before the boot screen, 0 skips it
+pub fn set_info_seconds(seconds: u8) -> Result<(), DisplayError> {
+    let mut config = config::load();
+    config.boot_info = seconds;
+    config::save(&config)
+}
+
+/// Shows device info if enabled, otherwise the boot screen right away.
+/// Has to be called after config::init
+pub fn start<T, const TEXT_ROW_LENGTH: usize>(
+    mode: &mut DisplayMode<TEXT_ROW_LENGTH>,
+    target: &mut T,
+) where
+    T: DrawTarget<Color = Rgb888> + Panel,
+{
+    //Stored value instead of current, Preview shows a changed setting before restart
+    let seconds = config::load().boot_info;
+
+    if seconds == 0 {
+        show(mode, target);
+        return;
+    }
+
+    //anim rate is at least 1 Hz
+    let ticks = (seconds as u32 * config::current().anim_rate as u32).min(u16::MAX as u32 - 1);
+
+    if let Some(tm) = mode.set_text_mode() {
+        info_screen(tm, target);
+    }
+    target.clear(Rgb888::new(0, 0, 0)).ok();
+    INFO_TICKS.store(ticks as u16 + 1, Ordering::Relaxed);
+}
+
+/// Counts down device info, called on every anim tick
+pub fn tick() {
+    let ticks = INFO_TICKS.load(Ordering::Relaxed);
+    if ticks > 1 {
+        INFO_TICKS.store(ticks - 1, Ordering::Relaxed);
+    }
+}
+
+/// Returns true once when device info was shown long enough and the boot screen should follow
+pub fn info_expired() -> bool {
+    INFO_TICKS
+        .compare_exchange(1, 0, Ordering::Relaxed, Ordering::Relaxed)
+        .is_ok()
+}
+
+/// Stops showing device info, returns true if it was shown
+pub fn cancel_info() -> bool {
+    INFO_TICKS.swap(0, Ordering::Relaxed) != 0
+}
+
+/// Shows the state saved by SaveState or the built-in screen if nothing was saved
+pub fn show<T, const TEXT_ROW_LENGTH: usize>(
+    mode: &mut DisplayMode<TEXT_ROW_LENGTH>,
+    target: &mut T,
+) where
+    T: DrawTarget<Color = Rgb888> + Panel,
+{
+    target.clear(Rgb888::new(0, 0, 0)).ok();
+
+    if !saved_state::restore(mode, target) {
+        if let Some(tm) = mode.set_text_mode() {
+            default_screen(tm);
+        }
+    }
+}
+
+fn default_screen<const TEXT_ROW_LENGTH: usize>(tm: &mut TextDisplay<TEXT_ROW_LENGTH>) {
+    tm.write(0, String::from("TEST")).ok();
+    tm.write(1, String::from("TEST")).ok();
+    tm.write(2, String::from("TEST")).ok();
+
+    tm.set_animation(
+        1,
+        TextAnimation::BlinkingAnimation(BlinkingAnimation::new(64)),
+    )
+    .ok();
+    tm.set_animation(
+        2,
+        TextAnimation::SlideAnimation(SlideAnimation::new(4, SlideDirection::Left)),
+    )
+    .ok();
+
+    tm.set_color(0, (128, 128, 128)).ok();
+    tm.set_color(1, (255, 0, 0)).ok();
+    tm.set_color(2, (240, 120, 0)).ok();
+
+    for (row, font) in config::current().fonts.iter().enumerate() {
+        tm.set_font(row, *font).ok();
+    }
+}
+
+//Firmware version, baud rate and panel size in separate rows
+fn info_screen<T, const TEXT_ROW_LENGTH: usize>(tm: &mut TextDisplay<TEXT_ROW_LENGTH>, target: &T)
+where
+    T: DrawTarget<Color = Rgb888> + Panel,
+{
+    let config = config::current();
+    let size = target.bounding_box().size;
+
+    let mut version = String::new();
+    write!(version, "UMX {}", env!("CARGO_PKG_VERSION")).ok();
+    let mut baud = String::new();
+    write!(baud, "{} Bd", config.baud_rate).ok();
+    let mut panel = String::new();
+    write!(panel, "{}x{}", size.width, size.height).ok();
+
+    tm.write(0, version).ok();
+    tm.write(1, baud).ok();
+    tm.write(2, panel).ok();
+
+    for (row, font) in config.fonts.iter().enumerate() {
+        tm.set_font(row, *font).ok();
+    }
+}
@@ -1,21 +1,38 @@
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::{
+    fmt::Write as _,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
 use crate::{
-    display::{font::Font, text_animations::TextAnimation, DisplayError, TextDisplay},
-    BlinkingAnimation, DisplayMode, SlideAnimation, SlideDirection,
+    boot_screen,
+    config::{self, ConfigKey},
+    display::{
+        brightness_schedule::BrightnessSchedule,
+        color_effects::ColorEffect,
+        font::{self, CustomFontHeader, Font},
+        panel::Panel,
+        text_animations::TextAnimation,
+        DisplayError, TextDisplay,
+    },
+    saved_state,
+    uart::RX_BUFFER_SIZE,
+    DisplayMode,
 };
 
 use embedded_graphics::{
     draw_target::DrawTarget,
-    pixelcolor::Rgb888,
+    pixelcolor::{Rgb888, RgbColor},
     prelude::{Point, Primitive},
     primitives::{Circle, Line, PrimitiveStyle, PrimitiveStyleBuilder, Rectangle, Triangle},
     Drawable, Pixel,
 };
-use heapless::String;
+use heapless::{String, Vec};
+use hub75::{ChainDirection, ChainLayout, Geometry, Orientation, Rotation, MAX_PANELS};
 
+/// buffer holds a command of length bytes followed by zero padding
 pub fn interpret_command<const TEXT_ROW_LENGTH: usize, const ROW_LENGTH: usize>(
     buffer: &[u8],
+    length: usize,
 ) -> Result<Command<TEXT_ROW_LENGTH, ROW_LENGTH>, DisplayError> {
     let command_id = buffer[0];
 
@@ -36,21 +53,90 @@ pub fn interpret_command<const TEXT_ROW_LENGTH: usize, const ROW_LENGTH: usize>(
         13 => Ok(Command::EnableOutput),
         14 => Ok(Command::DisableOutput),
         15 => Ok(Command::Ping),
+        16 => Ok(Command::SetBackground(SetBackground::new(&buffer)?)),
+        17 => Ok(Command::SetColorEffect(SetColorEffect::new(&buffer)?)),
+        18 => Ok(Command::UploadFont(UploadFont::new(&buffer)?)),
+        19 => Ok(Command::GetUnmappable(GetUnmappable::new(&buffer)?)),
+        20 => Ok(Command::SetBrightness(SetBrightness::new(&buffer)?)),
+        21 => Ok(Command::SetBrightnessSchedule(SetBrightnessSchedule::new(
+            &buffer,
+        )?)),
+        22 => Ok(Command::SetGamma(SetGamma::new(&buffer, length)?)),
+        23 => Ok(Command::SetWhiteBalance(SetWhiteBalance::new(&buffer)?)),
+        24 => Ok(Command::SetGeometry(SetGeometry::new(&buffer)?)),
+        25 => Ok(Command::SetChainLayout(SetChainLayout::new(&buffer)?)),
+        26 => Ok(Command::SetOrientation(SetOrientation::new(&buffer)?)),
+        27 => Ok(Command::SaveState(SaveState::new(&buffer)?)),
+        28 => Ok(Command::BootScreen(BootScreen::new(&buffer)?)),
+        29 => Ok(Command::GetConfig(GetConfig::new(&buffer)?)),
+        30 => Ok(Command::SetConfig(SetConfig::new(&buffer)?)),
+        31 => Ok(Command::ResetConfig),
+        32 => Ok(Command::GetRow(GetRow::new(&buffer)?)),
+        33 => Ok(Command::ReadPixel(ReadPixel::new(&buffer)?)),
+        34 => Ok(Command::ReadFramebuffer(ReadFramebuffer::new(&buffer)?)),
+        35 => Ok(Command::Screenshot(Screenshot::new(&buffer)?)),
+        36 => Ok(Command::DrawPixel(DrawPixel::new_wide(&buffer)?)),
+        37 => Ok(Command::DrawRow(DrawRow::new_segment(&buffer, length)?)),
         _ => Err(DisplayError::InvalidCommand),
     }
 }
 
+/// Increased when commands or their encoding change incompatibly
+pub const PROTOCOL_VERSION: u8 = 1;
+
+//Ids below are all handled by interpret_command
+const COMMAND_COUNT: u8 = 38;
+
+//Enough for ParamRequest and GetRow of the longest text
+pub const RESPONSE_LENGTH: usize = 384;
+
+pub enum Response {
+    Static(&'static str),
+    Text(String<RESPONSE_LENGTH>),
+    Data(Vec<u8, RESPONSE_LENGTH>),
+}
+
+impl Response {
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Response::Static(text) => text.as_bytes(),
+            Response::Text(text) => text.as_bytes(),
+            Response::Data(data) => data,
+        }
+    }
+}
+
 pub enum Command<const TEXT_ROW_LENGTH: usize, const ROW_LENGTH: usize> {
     Ping,
     ParamRequest,
     DisableOutput,
     EnableOutput,
     Clear,
+    SetBrightness(SetBrightness),
+    SetBrightnessSchedule(SetBrightnessSchedule),
+    SetGamma(SetGamma),
+    SetWhiteBalance(SetWhiteBalance),
+    SetGeometry(SetGeometry),
+    SetChainLayout(SetChainLayout),
+    SetOrientation(SetOrientation),
+    SaveState(SaveState),
+    BootScreen(BootScreen),
+    GetConfig(GetConfig),
+    SetConfig(SetConfig),
+    ResetConfig,
+    GetRow(GetRow),
+    ReadPixel(ReadPixel),
+    ReadFramebuffer(ReadFramebuffer),
+    Screenshot(Screenshot),
     SwitchMode(SwitchMode<TEXT_ROW_LENGTH>),
     Write(Write<TEXT_ROW_LENGTH>),
     SetFont(SetFont),
+    UploadFont(UploadFont),
+    GetUnmappable(GetUnmappable),
     SetColor(SetColor),
+    SetBackground(SetBackground),
     SetAnimation(SetAnimation),
+    SetColorEffect(SetColorEffect),
     DrawPixel(DrawPixel),
     DrawRow(DrawRow<ROW_LENGTH>),
     DrawLine(DrawLine),
@@ -60,28 +146,74 @@ pub enum Command<const TEXT_ROW_LENGTH: usize, const ROW_LENGTH: usize> {
 }
 
 impl<const TEXT_ROW_LENGTH: usize, const ROW_LENGTH: usize> Command<TEXT_ROW_LENGTH, ROW_LENGTH> {
-    pub fn execute<T: DrawTarget<Color = Rgb888>>(
+    pub fn execute<T: DrawTarget<Color = Rgb888> + Panel>(
         self,
         mode: &mut DisplayMode<TEXT_ROW_LENGTH>,
         target: &mut T,
         oe: &mut bool,
         clear_flag: &mut AtomicBool,
-    ) -> Result<&'static str, DisplayError> {
+        schedule: &mut BrightnessSchedule,
+    ) -> Result<Response, DisplayError> {
         match mode {
             DisplayMode::TextMode(text_display) => {
+                //Frame stays frozen for a screenshot until another command comes
+                if !matches!(self, Command::Screenshot(_)) {
+                    text_display.set_frozen(false);
+                }
+
                 match self {
                     Command::Write(write) => write.execute(text_display)?,
                     Command::SetFont(set_font) => set_font.execute(text_display)?,
+                    Command::UploadFont(upload_font) => upload_font.execute(text_display)?,
+                    Command::GetUnmappable(get_unmappable) => {
+                        return get_unmappable.execute(text_display)
+                    }
                     Command::SetColor(set_color) => set_color.execute(text_display)?,
+                    Command::SetBackground(set_background) => {
+                        set_background.execute(text_display)?
+                    }
                     Command::SetAnimation(set_animation) => set_animation.execute(text_display)?,
-                    Command::Ping => return Ok("PONG\n"),
-                    Command::ParamRequest => return Ok("Width:64;Height:32;Mode:Text\n"),
+                    Command::SetColorEffect(set_color_effect) => {
+                        set_color_effect.execute(text_display)?
+                    }
+                    Command::Ping => return Ok(Response::Static("PONG\n")),
+                    Command::ParamRequest => {
+                        return Ok(param_response::<_, TEXT_ROW_LENGTH>(target, "Text"))
+                    }
                     Command::DisableOutput => {
                         *oe = false;
                     }
                     Command::EnableOutput => {
                         *oe = true;
                     }
+                    Command::SetBrightness(set_brightness) => {
+                        set_brightness.execute(target, schedule)
+                    }
+                    Command::SetBrightnessSchedule(set_schedule) => {
+                        set_schedule.execute(target, schedule)?
+                    }
+                    Command::SetGamma(set_gamma) => set_gamma.execute(target),
+                    Command::SetWhiteBalance(set_white_balance) => {
+                        set_white_balance.execute(target)
+                    }
+                    Command::SetGeometry(set_geometry) => set_geometry.execute(target)?,
+                    Command::SetChainLayout(set_layout) => set_layout.execute(target)?,
+                    Command::SetOrientation(set_orientation) => set_orientation.execute(target),
+                    Command::SaveState(save_state) => save_state.execute(mode, target)?,
+                    //Restored framebuffer must not be cleared
+                    Command::BootScreen(boot_screen) => {
+                        boot_screen.execute(mode, target)?;
+                        return Ok(Response::Static("OK\n"));
+                    }
+                    Command::GetConfig(get_config) => return Ok(get_config.execute()),
+                    Command::SetConfig(set_config) => set_config.execute()?,
+                    Command::ResetConfig => config::reset()?,
+                    Command::GetRow(get_row) => return get_row.execute(text_display),
+                    Command::ReadPixel(read_pixel) => return read_pixel.execute(target),
+                    Command::ReadFramebuffer(read_framebuffer) => {
+                        return Ok(read_framebuffer.execute(target))
+                    }
+                    Command::Screenshot(screenshot) => return Ok(screenshot.execute(mode, target)),
                     Command::SwitchMode(switch_mode) => {
                         switch_mode.execute(mode, target)?;
                         clear_flag.store(true, Ordering::Relaxed);
@@ -99,11 +231,11 @@ impl<const TEXT_ROW_LENGTH: usize, const ROW_LENGTH: usize> Command<TEXT_ROW_LEN
                 Command::DrawCircle(draw_circle) => draw_circle.execute(target)?,
                 Command::Clear => {
                     target.clear(Rgb888::new(0, 0, 0)).ok();
-                    return Ok("OK\n");
+                    return Ok(Response::Static("OK\n"));
                 }
-                Command::Ping => return Ok("PONG\n"),
+                Command::Ping => return Ok(Response::Static("PONG\n")),
                 Command::ParamRequest => {
-                    return Ok("Width:64;Height:32;Mode:Direct\n");
+                    return Ok(param_response::<_, TEXT_ROW_LENGTH>(target, "Direct"))
                 }
                 Command::DisableOutput => {
                     *oe = false;
@@ -111,6 +243,25 @@ impl<const TEXT_ROW_LENGTH: usize, const ROW_LENGTH: usize> Command<TEXT_ROW_LEN
                 Command::EnableOutput => {
                     *oe = true;
                 }
+                Command::SetBrightness(set_brightness) => set_brightness.execute(target, schedule),
+                Command::SetBrightnessSchedule(set_schedule) => {
+                    set_schedule.execute(target, schedule)?
+                }
+                Command::SetGamma(set_gamma) => set_gamma.execute(target),
+                Command::SetWhiteBalance(set_white_balance) => set_white_balance.execute(target),
+                Command::SetGeometry(set_geometry) => set_geometry.execute(target)?,
+                Command::SetChainLayout(set_layout) => set_layout.execute(target)?,
+                Command::SetOrientation(set_orientation) => set_orientation.execute(target),
+                Command::SaveState(save_state) => save_state.execute(mode, target)?,
+                Command::BootScreen(boot_screen) => boot_screen.execute(mode, target)?,
+                Command::GetConfig(get_config) => return Ok(get_config.execute()),
+                Command::SetConfig(set_config) => set_config.execute()?,
+                Command::ResetConfig => config::reset()?,
+                Command::ReadPixel(read_pixel) => return read_pixel.execute(target),
+                Command::ReadFramebuffer(read_framebuffer) => {
+                    return Ok(read_framebuffer.execute(target))
+                }
+                Command::Screenshot(screenshot) => return Ok(screenshot.execute(mode, target)),
                 Command::SwitchMode(switch_mode) => {
                     switch_mode.execute(mode, target)?;
                 }
@@ -118,7 +269,541 @@ impl<const TEXT_ROW_LENGTH: usize, const ROW_LENGTH: usize> Command<TEXT_ROW_LEN
                 _ => return Err(DisplayError::IncorrectMode),
             },
         }
-        Ok("OK\n")
+        Ok(Response::Static("OK\n"))
+    }
+}
+
+pub struct SetBrightness {
+    brightness: u8,
+}
+
+impl SetBrightness {
+    pub fn new(buffer: &[u8]) -> Result<Self, DisplayError> {
+        let brightness = buffer[1];
+
+        Ok(SetBrightness { brightness })
+    }
+
+    /// Manually set brightness overrides the schedule
+    pub fn execute<T: Panel>(self, target: &mut T, schedule: &mut BrightnessSchedule) {
+        schedule.disable();
+        target.set_brightness(self.brightness);
+    }
+}
+
+pub struct SetBrightnessSchedule {
+    //None disables the schedule
+    settings: Option<BrightnessScheduleSettings>,
+}
+
+struct BrightnessScheduleSettings {
+    day_brightness: u8,
+    night_brightness: u8,
+    //minutes since midnight
+    night_start: u16,
+    night_end: u16,
+    time: u16,
+}
+
+impl SetBrightnessSchedule {
+    pub fn new(buffer: &[u8]) -> Result<Self, DisplayError> {
+        let read_u16 = |i: usize| u16::from_be_bytes([buffer[i], buffer[i + 1]]);
+
+        let settings = match buffer[1] {
+            0 => None,
+            1 => Some(BrightnessScheduleSettings {
+                day_brightness: buffer[2],
+                night_brightness: buffer[3],
+                night_start: read_u16(4),
+                night_end: read_u16(6),
+                time: read_u16(8),
+            }),
+            _ => return Err(DisplayError::InvalidSetting),
+        };
+
+        Ok(SetBrightnessSchedule { settings })
+    }
+
+    pub fn execute<T: Panel>(
+        self,
+        target: &mut T,
+        schedule: &mut BrightnessSchedule,
+    ) -> Result<(), DisplayError> {
+        match self.settings {
+            Some(settings) => {
+                schedule.enable(
+                    settings.day_brightness,
+                    settings.night_brightness,
+                    settings.night_start,
+                    settings.night_end,
+                    settings.time,
+                )?;
+            }
+            None => schedule.disable(),
+        }
+
+        if let Some(brightness) = schedule.brightness() {
+            target.set_brightness(brightness);
+        }
+
+        Ok(())
+    }
+}
+
+/// Capabilities of the device, fonts, animations and effects are listed in the order of their ids
+fn param_response<T, const TEXT_ROW_LENGTH: usize>(target: &T, mode: &str) -> Response
+where
+    T: DrawTarget<Color = Rgb888> + Panel,
+{
+    let size = target.bounding_box().size;
+    let geometry = target.geometry();
+    let mut response = String::new();
+
+    write!(
+        response,
+        "Firmware:{};Protocol:{};Width:{};Height:{};Mode:{};Scan:{};Panels:{};ColorDepth:{};",
+        env!("CARGO_PKG_VERSION"),
+        PROTOCOL_VERSION,
+        size.width,
+        size.height,
+        mode,
+        geometry.scan_rows,
+        target.layout().panels(),
+        target.color_depth()
+    )
+    .ok();
+
+    write!(
+        response,
+        "Commands:0-{};RxBuffer:{};TextLength:{};",
+        COMMAND_COUNT - 1,
+        RX_BUFFER_SIZE,
+        TEXT_ROW_LENGTH
+    )
+    .ok();
+
+    write_list(&mut response, "Fonts", &Font::NAMES);
+    write_list(&mut response, "Animations", &TextAnimation::NAMES);
+    write_list(&mut response, "Effects", &ColorEffect::NAMES);
+    response.pop();
+    response.push('\n').ok();
+
+    Response::Text(response)
+}
+
+//e.g. "Fonts:Default,ProFont;"
+fn write_list(response: &mut String<RESPONSE_LENGTH>, name: &str, items: &[&str]) {
+    write!(response, "{}:", name).ok();
+
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            response.push(',').ok();
+        }
+        response.push_str(item).ok();
+    }
+
+    response.push(';').ok();
+}
+
+pub struct SetGeometry {
+    geometry: Geometry,
+}
+
+impl SetGeometry {
+    pub fn new(buffer: &[u8]) -> Result<Self, DisplayError> {
+        let geometry = config::decode_geometry(&buffer[1..])?;
+
+        Ok(SetGeometry { geometry })
+    }
+
+    pub fn execute<T: Panel>(self, target: &mut T) -> Result<(), DisplayError> {
+        target.set_geometry(self.geometry)
+    }
+}
+
+pub struct SetChainLayout {
+    layout: ChainLayout,
+}
+
+impl SetChainLayout {
+    //command id, columns, rows and wiring
+    const ROTATIONS_OFFSET: usize = 4;
+
+    pub fn new(buffer: &[u8]) -> Result<Self, DisplayError> {
+        let columns = buffer[1];
+        let rows = buffer[2];
+        let panels = columns as usize * rows as usize;
+
+        if panels > MAX_PANELS {
+            return Err(DisplayError::OutOfBounds);
+        }
+
+        //bit 0 selects vertical chain, bit 1 serpentine wiring
+        let direction = match buffer[3] & 1 {
+            0 => ChainDirection::Horizontal,
+            _ => ChainDirection::Vertical,
+        };
+        let serpentine = buffer[3] & 2 != 0;
+
+        let mut layout = ChainLayout::new(columns, rows, direction, serpentine);
+
+        for (i, rotation) in layout.rotations[..panels].iter_mut().enumerate() {
+            *rotation = match buffer[Self::ROTATIONS_OFFSET + i] {
+                0 => Rotation::Normal,
+                1 => Rotation::Rotate90,
+                2 => Rotation::Rotate180,
+                3 => Rotation::Rotate270,
+                _ => return Err(DisplayError::InvalidSetting),
+            };
+        }
+
+        Ok(SetChainLayout { layout })
+    }
+
+    pub fn execute<T: Panel>(self, target: &mut T) -> Result<(), DisplayError> {
+        target.set_layout(self.layout)
+    }
+}
+
+pub struct SetOrientation {
+    orientation: Orientation,
+}
+
+impl SetOrientation {
+    pub fn new(buffer: &[u8]) -> Result<Self, DisplayError> {
+        //quarter turns clockwise, 0-3 for 0, 90, 180 and 270 degrees
+        let rotation = match buffer[1] {
+            0 => Rotation::Normal,
+            1 => Rotation::Rotate90,
+            2 => Rotation::Rotate180,
+            3 => Rotation::Rotate270,
+            _ => return Err(DisplayError::InvalidSetting),
+        };
+
+        //bit 0 mirrors horizontally, bit 1 vertically
+        let flip_horizontal = buffer[2] & 1 != 0;
+        let flip_vertical = buffer[2] & 2 != 0;
+
+        Ok(SetOrientation {
+            orientation: Orientation::new(rotation, flip_horizontal, flip_vertical),
+        })
+    }
+
+    /// Pixels already drawn in direct mode stay where they are
+    pub fn execute<T: Panel>(self, target: &mut T) {
+        target.set_orientation(self.orientation);
+    }
+}
+
+pub struct SaveState {
+    erase: bool,
+}
+
+impl SaveState {
+    pub fn new(buffer: &[u8]) -> Result<Self, DisplayError> {
+        //0 saves current mode and its content, 1 removes saved state
+        let erase = match buffer[1] {
+            0 => false,
+            1 => true,
+            _ => return Err(DisplayError::InvalidSetting),
+        };
+
+        Ok(SaveState { erase })
+    }
+
+    /// Saved state is restored at boot instead of the default screen
+    pub fn execute<T: Panel, const TEXT_ROW_LENGTH: usize>(
+        self,
+        mode: &DisplayMode<TEXT_ROW_LENGTH>,
+        target: &T,
+    ) -> Result<(), DisplayError> {
+        if self.erase {
+            saved_state::erase()
+        } else {
+            saved_state::save(mode, target)
+        }
+    }
+}
+
+/// Content of the boot screen is set by SaveState
+pub enum BootScreen {
+    /// Shows the boot screen as after a restart
+    Preview,
+    /// Seconds device info is shown before the boot screen, 0 disables it
+    SetInfo(u8),
+}
+
+impl BootScreen {
+    pub fn new(buffer: &[u8]) -> Result<Self, DisplayError> {
+        match buffer[1] {
+            0 => Ok(BootScreen::Preview),
+            1 => Ok(BootScreen::SetInfo(buffer[2])),
+            _ => Err(DisplayError::InvalidSetting),
+        }
+    }
+
+    pub fn execute<T, const TEXT_ROW_LENGTH: usize>(
+        self,
+        mode: &mut DisplayMode<TEXT_ROW_LENGTH>,
+        target: &mut T,
+    ) -> Result<(), DisplayError>
+    where
+        T: DrawTarget<Color = Rgb888> + Panel,
+    {
+        match self {
+            BootScreen::Preview => {
+                boot_screen::start(mode, target);
+                Ok(())
+            }
+            BootScreen::SetInfo(seconds) => boot_screen::set_info_seconds(seconds),
+        }
+    }
+}
+
+pub struct GetConfig {
+    key: ConfigKey,
+}
+
+impl GetConfig {
+    pub fn new(buffer: &[u8]) -> Result<Self, DisplayError> {
+        let key = ConfigKey::from_id(buffer[1]).ok_or(DisplayError::InvalidSetting)?;
+
+        Ok(GetConfig { key })
+    }
+
+    /// Returns the stored value, which can differ from the one in use until restart
+    pub fn execute(self) -> Response {
+        let mut response = String::new();
+        config::load().describe(self.key, &mut response);
+        response.push('\n').ok();
+
+        Response::Text(response)
+    }
+}
+
+const CONFIG_VALUE_LENGTH: usize = 32;
+
+pub struct SetConfig {
+    key: ConfigKey,
+    value: Vec<u8, CONFIG_VALUE_LENGTH>,
+}
+
+impl SetConfig {
+    //command id and key
+    const VALUE_OFFSET: usize = 2;
+
+    pub fn new(buffer: &[u8]) -> Result<Self, DisplayError> {
+        let key = ConfigKey::from_id(buffer[1]).ok_or(DisplayError::InvalidSetting)?;
+
+        let end = buffer.len().min(Self::VALUE_OFFSET + CONFIG_VALUE_LENGTH);
+        let value = Vec::from_slice(&buffer[Self::VALUE_OFFSET..end])
+            .map_err(|_| DisplayError::OutOfBounds)?;
+
+        Ok(SetConfig { key, value })
+    }
+
+    /// Stores the value, it's used after restart
+    pub fn execute(self) -> Result<(), DisplayError> {
+        let mut config = config::load();
+        config.set(self.key, &self.value)?;
+        config::save(&config)
+    }
+}
+
+pub struct ReadPixel {
+    x: i32,
+    y: i32,
+}
+
+impl ReadPixel {
+    pub fn new(buffer: &[u8]) -> Result<Self, DisplayError> {
+        let x = u16::from_be_bytes([buffer[1], buffer[2]]) as i32;
+        let y = u16::from_be_bytes([buffer[3], buffer[4]]) as i32;
+
+        Ok(ReadPixel { x, y })
+    }
+
+    /// Returns the color as drawn, e.g. "Pixel:255,0,119". Bits below ColorDepth are lost
+    pub fn execute<T: Panel>(self, target: &T) -> Result<Response, DisplayError> {
+        let color = target
+            .pixel_color(self.x, self.y)
+            .ok_or(DisplayError::OutOfBounds)?;
+
+        let mut response = String::new();
+        writeln!(response, "Pixel:{},{},{}", color.r(), color.g(), color.b()).ok();
+
+        Ok(Response::Text(response))
+    }
+}
+
+//r, g, b of every pixel in a data response
+const MAX_CHUNK_PIXELS: usize = RESPONSE_LENGTH / 3;
+
+/// Returns r, g, b bytes of count pixels from start as drawn, counted left to right
+/// and top to bottom. Stops early at the end of the drawing area
+fn read_pixels<T>(target: &T, start: usize, count: usize) -> Response
+where
+    T: DrawTarget<Color = Rgb888> + Panel,
+{
+    let width = target.bounding_box().size.width as usize;
+    let mut data = Vec::new();
+
+    for index in start..start + count {
+        let (x, y) = ((index % width) as i32, (index / width) as i32);
+
+        match target.pixel_color(x, y) {
+            Some(color) => data
+                .extend_from_slice(&[color.r(), color.g(), color.b()])
+                .ok(),
+            None => break,
+        };
+    }
+
+    Response::Data(data)
+}
+
+//first pixel and pixel count
+fn read_chunk(buffer: &[u8]) -> Result<(usize, usize), DisplayError> {
+    let start = u16::from_be_bytes([buffer[0], buffer[1]]) as usize;
+    let count = buffer[2] as usize;
+
+    if count > MAX_CHUNK_PIXELS {
+        return Err(DisplayError::OutOfBounds);
+    }
+
+    Ok((start, count))
+}
+
+pub struct ReadFramebuffer {
+    start: usize,
+    count: usize,
+}
+
+impl ReadFramebuffer {
+    pub fn new(buffer: &[u8]) -> Result<Self, DisplayError> {
+        let (start, count) = read_chunk(&buffer[1..])?;
+
+        Ok(ReadFramebuffer { start, count })
+    }
+
+    /// RGB888 as drawn, see read_pixels. Bits below ColorDepth are lost
+    pub fn execute<T: DrawTarget<Color = Rgb888> + Panel>(self, target: &T) -> Response {
+        read_pixels(target, self.start, self.count)
+    }
+}
+
+pub enum Screenshot {
+    /// Stops text animations, so all chunks come from the same frame
+    Begin,
+    Chunk {
+        start: usize,
+        count: usize,
+    },
+    /// Resumes animations, any other command does too
+    End,
+}
+
+impl Screenshot {
+    pub fn new(buffer: &[u8]) -> Result<Self, DisplayError> {
+        match buffer[1] {
+            0 => Ok(Screenshot::Begin),
+            1 => {
+                let (start, count) = read_chunk(&buffer[2..])?;
+                Ok(Screenshot::Chunk { start, count })
+            }
+            2 => Ok(Screenshot::End),
+            _ => Err(DisplayError::InvalidSetting),
+        }
+    }
+
+    /// Begin returns the frame size and most pixels per chunk, e.g. "Width:64;Height:32;Chunk:128".
+    /// Chunks are RGB888 as drawn, see read_pixels
+    pub fn execute<T, const TEXT_ROW_LENGTH: usize>(
+        self,
+        mode: &mut DisplayMode<TEXT_ROW_LENGTH>,
+        target: &T,
+    ) -> Response
+    where
+        T: DrawTarget<Color = Rgb888> + Panel,
+    {
+        match self {
+            Screenshot::Begin => {
+                if let DisplayMode::TextMode(text_display) = mode {
+                    text_display.set_frozen(true);
+                }
+
+                let size = target.bounding_box().size;
+                let mut response = String::new();
+                writeln!(
+                    response,
+                    "Width:{};Height:{};Chunk:{}",
+                    size.width, size.height, MAX_CHUNK_PIXELS
+                )
+                .ok();
+
+                Response::Text(response)
+            }
+            Screenshot::Chunk { start, count } => read_pixels(target, start, count),
+            Screenshot::End => {
+                if let DisplayMode::TextMode(text_display) = mode {
+                    text_display.set_frozen(false);
+                }
+
+                Response::Static("OK\n")
+            }
+        }
+    }
+}
+
+pub struct SetGamma {
+    table: [u8; 256],
+}
+
+impl SetGamma {
+    //command id and curve
+    const TABLE_OFFSET: usize = 2;
+
+    pub fn new(buffer: &[u8], length: usize) -> Result<Self, DisplayError> {
+        let table = match buffer[1] {
+            0 => hub75::gamma_2_0(),
+            1 => hub75::GAMMA_2_8,
+            2 => hub75::linear(),
+            3 => {
+                //A short table must not be completed by the padding
+                let data = buffer[..length.min(buffer.len())]
+                    .get(Self::TABLE_OFFSET..Self::TABLE_OFFSET + 256)
+                    .ok_or(DisplayError::OutOfBounds)?;
+
+                let mut table = [0; 256];
+                table.copy_from_slice(data);
+                table
+            }
+            _ => return Err(DisplayError::InvalidSetting),
+        };
+
+        Ok(SetGamma { table })
+    }
+
+    /// Pixels already drawn in direct mode keep previous correction
+    pub fn execute<T: Panel>(self, target: &mut T) {
+        target.set_gamma(&self.table);
+    }
+}
+
+pub struct SetWhiteBalance {
+    gains: (u8, u8, u8),
+}
+
+impl SetWhiteBalance {
+    pub fn new(buffer: &[u8]) -> Result<Self, DisplayError> {
+        let gains = (buffer[1], buffer[2], buffer[3]);
+
+        Ok(SetWhiteBalance { gains })
+    }
+
+    pub fn execute<T: Panel>(self, target: &mut T) {
+        let (r, g, b) = self.gains;
+        target.set_white_balance(r, g, b);
     }
 }
 
@@ -139,7 +824,7 @@ impl<const TEXT_ROW_LENGTH: usize> SwitchMode<TEXT_ROW_LENGTH> {
     ) -> Result<(), DisplayError> {
         match self.mode {
             0 => {
-                *mode = DisplayMode::TextMode(TextDisplay::new());
+                mode.set_text_mode();
                 target.clear(Rgb888::new(0, 0, 0)).ok();
             }
             1 => {
@@ -186,14 +871,7 @@ pub struct SetFont {
 impl SetFont {
     pub fn new(buffer: &[u8]) -> Result<Self, DisplayError> {
         let row = buffer[1] as usize;
-        let font = match buffer[2] {
-            0 => Font::Default,
-            1 => Font::ProFont,
-            2 => Font::Ibm,
-            _ => {
-                return Err(DisplayError::InvalidSetting);
-            }
-        };
+        let font = Font::from_id(buffer[2]).ok_or(DisplayError::InvalidSetting)?;
 
         Ok(SetFont { font, row })
     }
@@ -208,6 +886,122 @@ impl SetFont {
     }
 }
 
+const FONT_CHUNK_SIZE: usize = 256;
+
+//Moved once from the parser to execute, the size of a chunk doesn't matter
+#[allow(clippy::large_enum_variant)]
+pub enum UploadFont {
+    Begin(CustomFontHeader),
+    Data {
+        offset: usize,
+        data: Vec<u8, FONT_CHUNK_SIZE>,
+    },
+    Commit,
+}
+
+impl UploadFont {
+    //command id, stage, offset and length
+    const DATA_OFFSET: usize = 6;
+
+    pub fn new(buffer: &[u8]) -> Result<Self, DisplayError> {
+        match buffer[1] {
+            0 => {
+                let header = CustomFontHeader {
+                    glyph_width: buffer[2],
+                    glyph_height: buffer[3],
+                    baseline: buffer[4],
+                    first_char: u32::from_be_bytes([buffer[5], buffer[6], buffer[7], buffer[8]]),
+                    char_count: u16::from_be_bytes([buffer[9], buffer[10]]),
+                };
+
+                //Rejected before execute, so rows keep the custom font
+                if !header.is_valid() {
+                    return Err(DisplayError::InvalidSetting);
+                }
+
+                Ok(UploadFont::Begin(header))
+            }
+            1 => {
+                let offset = u16::from_be_bytes([buffer[2], buffer[3]]) as usize;
+                let length = u16::from_be_bytes([buffer[4], buffer[5]]) as usize;
+
+                if length > FONT_CHUNK_SIZE || Self::DATA_OFFSET + length > buffer.len() {
+                    return Err(DisplayError::OutOfBounds);
+                }
+
+                let data = Vec::from_slice(&buffer[Self::DATA_OFFSET..Self::DATA_OFFSET + length])
+                    .map_err(|_| DisplayError::OutOfBounds)?;
+
+                Ok(UploadFont::Data { offset, data })
+            }
+            2 => Ok(UploadFont::Commit),
+            _ => Err(DisplayError::InvalidSetting),
+        }
+    }
+
+    pub fn execute<const TEXT_ROW_LENGTH: usize>(
+        self,
+        target: &mut TextDisplay<TEXT_ROW_LENGTH>,
+    ) -> Result<(), DisplayError> {
+        match self {
+            UploadFont::Begin(header) => {
+                target.release_custom_font();
+                font::begin_custom_font(header)
+            }
+            UploadFont::Data { offset, data } => font::write_custom_font(offset, &data),
+            UploadFont::Commit => font::commit_custom_font(),
+        }
+    }
+}
+
+pub struct GetUnmappable {
+    row: usize,
+}
+
+impl GetUnmappable {
+    pub fn new(buffer: &[u8]) -> Result<Self, DisplayError> {
+        let row = buffer[1] as usize;
+
+        Ok(GetUnmappable { row })
+    }
+
+    pub fn execute<const TEXT_ROW_LENGTH: usize>(
+        self,
+        target: &mut TextDisplay<TEXT_ROW_LENGTH>,
+    ) -> Result<Response, DisplayError> {
+        let mut response = String::new();
+
+        response.push_str(target.unmapped(self.row)?).ok();
+        response.push('\n').ok();
+
+        Ok(Response::Text(response))
+    }
+}
+
+pub struct GetRow {
+    row: usize,
+}
+
+impl GetRow {
+    pub fn new(buffer: &[u8]) -> Result<Self, DisplayError> {
+        let row = buffer[1] as usize;
+
+        Ok(GetRow { row })
+    }
+
+    pub fn execute<const TEXT_ROW_LENGTH: usize>(
+        self,
+        target: &TextDisplay<TEXT_ROW_LENGTH>,
+    ) -> Result<Response, DisplayError> {
+        let mut response = String::new();
+
+        target.describe_row(self.row, &mut response)?;
+        response.push('\n').ok();
+
+        Ok(Response::Text(response))
+    }
+}
+
 pub struct SetColor {
     rgb_color: (u8, u8, u8),
     row: usize,
@@ -231,6 +1025,34 @@ impl SetColor {
     }
 }
 
+pub struct SetBackground {
+    //None means transparent background
+    rgb_color: Option<(u8, u8, u8)>,
+    row: usize,
+}
+
+impl SetBackground {
+    pub fn new(buffer: &[u8]) -> Result<Self, DisplayError> {
+        let row = buffer[1] as usize;
+        let rgb_color = match buffer[2] {
+            0 => Some((buffer[3], buffer[4], buffer[5])),
+            1 => None,
+            _ => return Err(DisplayError::InvalidSetting),
+        };
+
+        Ok(SetBackground { rgb_color, row })
+    }
+
+    pub fn execute<const TEXT_ROW_LENGTH: usize>(
+        self,
+        target: &mut TextDisplay<TEXT_ROW_LENGTH>,
+    ) -> Result<(), DisplayError> {
+        target.set_background(self.row, self.rgb_color)?;
+
+        Ok(())
+    }
+}
+
 pub struct SetAnimation {
     animation: TextAnimation,
     row: usize,
@@ -239,23 +1061,7 @@ pub struct SetAnimation {
 impl SetAnimation {
     pub fn new(buffer: &[u8]) -> Result<Self, DisplayError> {
         let row = buffer[1] as usize;
-        let animation = match buffer[2] {
-            0 => TextAnimation::NoAnimation,
-            1 => {
-                let anim = BlinkingAnimation::new(buffer[3] as i32);
-                TextAnimation::BlinkingAnimation(anim)
-            }
-            2 => {
-                let dir = match buffer[4] {
-                    1 => SlideDirection::Right,
-                    _ => SlideDirection::Left,
-                };
-
-                let anim = SlideAnimation::new(buffer[3] as i32, dir);
-                TextAnimation::SlideAnimation(anim)
-            }
-            _ => return Err(DisplayError::InvalidSetting),
-        };
+        let animation = TextAnimation::decode(&buffer[2..]).ok_or(DisplayError::InvalidSetting)?;
 
         Ok(SetAnimation { animation, row })
     }
@@ -270,6 +1076,29 @@ impl SetAnimation {
     }
 }
 
+pub struct SetColorEffect {
+    color_effect: ColorEffect,
+    row: usize,
+}
+
+impl SetColorEffect {
+    pub fn new(buffer: &[u8]) -> Result<Self, DisplayError> {
+        let row = buffer[1] as usize;
+        let color_effect = ColorEffect::decode(&buffer[2..]).ok_or(DisplayError::InvalidSetting)?;
+
+        Ok(SetColorEffect { color_effect, row })
+    }
+
+    pub fn execute<const TEXT_ROW_LENGTH: usize>(
+        self,
+        target: &mut TextDisplay<TEXT_ROW_LENGTH>,
+    ) -> Result<(), DisplayError> {
+        target.set_color_effect(self.row, self.color_effect)?;
+
+        Ok(())
+    }
+}
+
 pub struct DrawPixel {
     rgb_color: (u8, u8, u8),
     coords: (usize, usize),
@@ -280,9 +1109,15 @@ impl DrawPixel {
         let coords = (buffer[1] as usize, buffer[2] as usize);
         let rgb_color = (buffer[3], buffer[4], buffer[5]);
 
-        if coords.0 > 63 || coords.1 > 31 {
-            return Err(DisplayError::OutOfBounds);
-        }
+        Ok(DrawPixel { rgb_color, coords })
+    }
+
+    /// Coordinates as u16, for canvases with more than 256 pixels on a side
+    pub fn new_wide(buffer: &[u8]) -> Result<Self, DisplayError> {
+        let read_u16 = |i: usize| u16::from_be_bytes([buffer[i], buffer[i + 1]]) as usize;
+
+        let coords = (read_u16(1), read_u16(3));
+        let rgb_color = (buffer[5], buffer[6], buffer[7]);
 
         Ok(DrawPixel { rgb_color, coords })
     }
@@ -292,9 +1127,17 @@ impl DrawPixel {
         target: &mut T,
     ) -> Result<(), DisplayError> {
         let (x, y) = self.coords;
-        let (r, g, b) = self.rgb_color;
+        let (red, green, blue) = self.rgb_color;
+        let size = target.bounding_box().size;
 
-        let pixel = Pixel(Point::new(x as i32, y as i32), Rgb888::new(r, g, b));
+        if x >= size.width as usize || y >= size.height as usize {
+            return Err(DisplayError::OutOfBounds);
+        }
+
+        let pixel = Pixel(
+            Point::new(x as i32, y as i32),
+            Rgb888::new(red, green, blue),
+        );
 
         pixel.draw(target).map_err(|_| DisplayError::DrawError)?;
 
@@ -305,25 +1148,54 @@ impl DrawPixel {
 pub struct DrawRow<const ROW_LENGTH: usize> {
     rgb_color: [(u8, u8, u8); ROW_LENGTH],
     row: usize,
+    //First column and number of pixels in rgb_color
+    x: usize,
+    length: usize,
 }
 
 impl<const ROW_LENGTH: usize> DrawRow<ROW_LENGTH> {
     pub fn new(buffer: &[u8]) -> Result<Self, DisplayError> {
         let row = buffer[1] as usize;
 
-        if row > 31 {
+        Ok(Self {
+            rgb_color: Self::read_colors(buffer, 2, ROW_LENGTH),
+            row,
+            x: 0,
+            length: ROW_LENGTH,
+        })
+    }
+
+    /// Up to ROW_LENGTH pixels starting at given column, so rows wider than that
+    /// are drawn in several segments. Row, column and pixel count are u16
+    pub fn new_segment(buffer: &[u8], length: usize) -> Result<Self, DisplayError> {
+        //command id, row, column and pixel count
+        const COLORS_OFFSET: usize = 7;
+
+        let read_u16 = |i: usize| u16::from_be_bytes([buffer[i], buffer[i + 1]]) as usize;
+        let count = read_u16(5);
+
+        if count > ROW_LENGTH || COLORS_OFFSET + count * 3 > length {
             return Err(DisplayError::OutOfBounds);
         }
 
+        Ok(Self {
+            rgb_color: Self::read_colors(buffer, COLORS_OFFSET, count),
+            row: read_u16(1),
+            x: read_u16(3),
+            length: count,
+        })
+    }
+
+    fn read_colors(buffer: &[u8], offset: usize, count: usize) -> [(u8, u8, u8); ROW_LENGTH] {
         let mut rgb_color = [(0, 0, 0); ROW_LENGTH];
 
-        (0..ROW_LENGTH).for_each(|i| {
-            let offset = 2 + (i * 3);
+        (0..count).for_each(|i| {
+            let offset = offset + (i * 3);
             let color = (buffer[offset], buffer[offset + 1], buffer[offset + 2]);
             rgb_color[i] = color;
         });
 
-        Ok(Self { rgb_color, row })
+        rgb_color
     }
 
     pub fn execute<T: DrawTarget<Color = Rgb888>>(
@@ -331,10 +1203,24 @@ impl<const ROW_LENGTH: usize> DrawRow<ROW_LENGTH> {
         target: &mut T,
     ) -> Result<(), DisplayError> {
         let y = self.row;
+        let size = target.bounding_box().size;
+
+        if y >= size.height as usize || self.x >= size.width as usize {
+            return Err(DisplayError::OutOfBounds);
+        }
 
-        let pixels =
-            self.rgb_color.iter().enumerate().map(|(x, (r, g, b))| {
-                Pixel(Point::new(x as i32, y as i32), Rgb888::new(*r, *g, *b))
+        //Rows longer than the canvas are cut off
+        let length = self.length.min(size.width as usize - self.x);
+        let x = self.x;
+
+        let pixels = self.rgb_color[..length]
+            .iter()
+            .enumerate()
+            .map(|(i, (r, g, b))| {
+                Pixel(
+                    Point::new((x + i) as i32, y as i32),
+                    Rgb888::new(*r, *g, *b),
+                )
             });
 
         target
@@ -0,0 +1,55 @@
+pub use umx_core::config::{decode_geometry, ConfigKey, ScreenFont, FONTS, FRAMEBUFFER_WORDS};
+
+use crate::{
+    display::{font::Font, DisplayError},
+    storage::Record,
+};
+
+//Page below the saved state, reserved in memory.x
+const STORE: Record = Record::new(0xEC00, 1, 1);
+
+pub type Config = umx_core::config::Config<Font>;
+
+static mut CURRENT: Config = Config::DEFAULT;
+
+impl ScreenFont for Font {
+    const DEFAULTS: [Font; FONTS] = [Font::Default, Font::Ibm, Font::ProFont];
+
+    fn from_stored_id(id: u8) -> Option<Font> {
+        match Font::from_id(id) {
+            //Custom font is lost on restart
+            Some(Font::Custom) | None => None,
+            font => font,
+        }
+    }
+
+    fn stored_id(&self) -> u8 {
+        self.id()
+    }
+}
+
+/// Loads the stored configuration, has to be called after storage::init and before current
+pub fn init() {
+    unsafe {
+        CURRENT = load();
+    }
+}
+
+/// Configuration the device was started with, stored changes apply after restart
+pub fn current() -> &'static Config {
+    unsafe { &CURRENT }
+}
+
+/// Defaults overridden by the stored values
+pub fn load() -> Config {
+    Config::load(STORE.load())
+}
+
+pub fn save(config: &Config) -> Result<(), DisplayError> {
+    STORE.save(|writer| config.save(writer))
+}
+
+/// Removes stored values, defaults apply after restart
+pub fn reset() -> Result<(), DisplayError> {
+    STORE.erase()
+}
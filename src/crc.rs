@@ -37,7 +37,7 @@ const CRC_TABLE : [u8; 256] = [
 
 
 pub fn crc8_ccitt_single(byte: u8) -> u8 {
-    return CRC_TABLE[(0 ^ byte) as usize]
+    CRC_TABLE[byte as usize]
 }
 
 pub fn crc8_ccitt(data: &[u8]) -> u8{
@@ -47,7 +47,7 @@ pub fn crc8_ccitt(data: &[u8]) -> u8{
 		val = CRC_TABLE[(val ^ byte) as usize];
 	}
 
-	return val;
+	val
 }
 
 pub fn crc8_ccitt_response(cc: u8, data: &[u8]) -> u8{
@@ -56,5 +56,5 @@ pub fn crc8_ccitt_response(cc: u8, data: &[u8]) -> u8{
 		val = CRC_TABLE[(val ^ byte) as usize];
 	}
 
-    return val;
+    val
 }
@@ -1,31 +1,33 @@
+pub mod brightness_schedule;
+pub mod charmap;
+pub mod color_effects;
 pub mod direct_display;
 pub mod text_display;
 pub mod text_animations;
 pub mod font;
+pub mod panel;
 
 pub use text_display::TextDisplay;
+pub use umx_core::{proportional_font, DisplayError};
 
-pub enum DisplayError{
-    OutOfBounds,
-    IncorrectMode,
-    InvalidSetting,
-    InvalidCommand,
-    DrawError,
+//DirectMode is the initial value of a static, with explicit tag 0 it's all zeros and the
+//static takes no flash for its initial value
+#[repr(u8)]
+pub enum DisplayMode<'a, const MAX_ROW_LENGTH: usize>{
+    DirectMode,
+    TextMode(TextDisplay<'a, MAX_ROW_LENGTH>),
 }
 
-impl DisplayError{
-    pub fn message(&self) -> &'static str{
+impl<'a, const MAX_ROW_LENGTH: usize> DisplayMode<'a, MAX_ROW_LENGTH> {
+    /// Switches to text mode with empty rows and returns it. The only place building
+    /// a new TextDisplay, so the stack holds it once
+    #[inline(never)]
+    pub fn set_text_mode(&mut self) -> Option<&mut TextDisplay<'a, MAX_ROW_LENGTH>> {
+        *self = DisplayMode::TextMode(TextDisplay::new());
+
         match self {
-            DisplayError::OutOfBounds => "Index out of Bounds",
-            DisplayError::IncorrectMode => "Incorrect Mode",
-            DisplayError::InvalidSetting => "Invalid Setting",
-            DisplayError::InvalidCommand => "Invalid Command",
-            DisplayError::DrawError => "Drawing Error",
+            DisplayMode::TextMode(text_display) => Some(text_display),
+            DisplayMode::DirectMode => None,
         }
     }
 }
-
-pub enum DisplayMode<'a, const MAX_ROW_LENGTH: usize>{
-    TextMode(TextDisplay<'a, MAX_ROW_LENGTH>),
-    DirectMode
-}
\ No newline at end of file
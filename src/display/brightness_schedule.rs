@@ -0,0 +1,92 @@
+use super::DisplayError;
+
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+/// Switches between day and night brightness based on a software clock.
+/// The clock has to be set by the host and drifts with the tick source.
+pub struct BrightnessSchedule {
+    enabled: bool,
+    day_brightness: u8,
+    night_brightness: u8,
+    //minutes since midnight
+    night_start: u16,
+    night_end: u16,
+    time: u16,
+    ticks: u32,
+    ticks_per_minute: u32,
+}
+
+impl BrightnessSchedule {
+    pub const fn new(ticks_per_minute: u32) -> Self {
+        BrightnessSchedule {
+            enabled: false,
+            day_brightness: 255,
+            night_brightness: 255,
+            night_start: 0,
+            night_end: 0,
+            time: 0,
+            ticks: 0,
+            ticks_per_minute,
+        }
+    }
+
+    pub fn enable(
+        &mut self,
+        day_brightness: u8,
+        night_brightness: u8,
+        night_start: u16,
+        night_end: u16,
+        time: u16,
+    ) -> Result<(), DisplayError> {
+        if night_start >= MINUTES_PER_DAY || night_end >= MINUTES_PER_DAY || time >= MINUTES_PER_DAY
+        {
+            return Err(DisplayError::InvalidSetting);
+        }
+
+        self.enabled = true;
+        self.day_brightness = day_brightness;
+        self.night_brightness = night_brightness;
+        self.night_start = night_start;
+        self.night_end = night_end;
+        self.time = time;
+        self.ticks = 0;
+
+        Ok(())
+    }
+
+    pub fn disable(&mut self) {
+        self.enabled = false;
+    }
+
+    /// Advances the clock, returns brightness that should be applied if the schedule is enabled
+    pub fn tick(&mut self) -> Option<u8> {
+        self.ticks += 1;
+        if self.ticks >= self.ticks_per_minute {
+            self.ticks = 0;
+            self.time = (self.time + 1) % MINUTES_PER_DAY;
+        }
+
+        self.brightness()
+    }
+
+    pub fn brightness(&self) -> Option<u8> {
+        if !self.enabled {
+            return None;
+        }
+
+        if self.is_night() {
+            Some(self.night_brightness)
+        } else {
+            Some(self.day_brightness)
+        }
+    }
+
+    fn is_night(&self) -> bool {
+        if self.night_start <= self.night_end {
+            self.night_start <= self.time && self.time < self.night_end
+        } else {
+            //night goes over midnight
+            self.time >= self.night_start || self.time < self.night_end
+        }
+    }
+}
@@ -0,0 +1,9 @@
+pub use umx_core::charmap::{contains_char, CP437_HIGH, REPLACEMENT_CHAR};
+
+use super::font::Font;
+
+/// Returns the character that should be drawn in place of c with the given font,
+/// or None if the font has no reasonable substitute
+pub fn map_char(font: Font, c: char) -> Option<char> {
+    umx_core::charmap::map_char(c, |c| font.has_glyph(c))
+}
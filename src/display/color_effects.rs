@@ -0,0 +1,238 @@
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    pixelcolor::{BinaryColor, Rgb888, RgbColor},
+    prelude::Dimensions,
+    primitives::Rectangle,
+    Pixel,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub enum ColorEffect {
+    NoEffect,
+    GradientEffect(GradientEffect),
+    RainbowEffect(RainbowEffect),
+    PulseEffect(PulseEffect),
+}
+
+impl ColorEffect {
+    /// Names in the order of ids used by decode
+    pub const NAMES: [&'static str; 4] = ["None", "Gradient", "Rainbow", "Pulse"];
+
+    pub fn tick(&mut self) {
+        match self {
+            ColorEffect::RainbowEffect(effect) => effect.tick(),
+            ColorEffect::PulseEffect(effect) => effect.tick(),
+            _ => {}
+        }
+    }
+
+    /// Returns the color of a text pixel in column x of a row that is width pixels wide
+    pub fn get(&self, x: i32, width: i32, color: Rgb888) -> Rgb888 {
+        match self {
+            ColorEffect::GradientEffect(effect) => effect.get(x, width),
+            ColorEffect::RainbowEffect(effect) => effect.get(x),
+            ColorEffect::PulseEffect(effect) => effect.get(color),
+            ColorEffect::NoEffect => color,
+        }
+    }
+
+    /// Kind followed by gradient colors or tempo, as in SetColorEffect command
+    pub fn encode(&self) -> [u8; 7] {
+        match self {
+            ColorEffect::NoEffect => [0; 7],
+            ColorEffect::GradientEffect(effect) => {
+                let (from, to) = (effect.from, effect.to);
+                [1, from.r(), from.g(), from.b(), to.r(), to.g(), to.b()]
+            }
+            ColorEffect::RainbowEffect(effect) => [2, effect.tempo as u8, 0, 0, 0, 0, 0],
+            ColorEffect::PulseEffect(effect) => [3, effect.tempo as u8, 0, 0, 0, 0, 0],
+        }
+    }
+
+    /// Restarts the effect from settings returned by encode
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let effect = match data[0] {
+            0 => ColorEffect::NoEffect,
+            1 => {
+                let from = Rgb888::new(data[1], data[2], data[3]);
+                let to = Rgb888::new(data[4], data[5], data[6]);
+                ColorEffect::GradientEffect(GradientEffect::new(from, to))
+            }
+            2 => ColorEffect::RainbowEffect(RainbowEffect::new(data[1] as i32)),
+            3 => ColorEffect::PulseEffect(PulseEffect::new(data[1] as i32)),
+            _ => return None,
+        };
+
+        Some(effect)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct GradientEffect {
+    from: Rgb888,
+    to: Rgb888,
+}
+
+impl GradientEffect {
+    pub fn new(from: Rgb888, to: Rgb888) -> Self {
+        GradientEffect { from, to }
+    }
+
+    pub fn get(&self, x: i32, width: i32) -> Rgb888 {
+        let last_column = (width - 1).max(1);
+        let x = x.max(0).min(last_column);
+
+        let mix = |a: u8, b: u8| (a as i32 + (b as i32 - a as i32) * x / last_column) as u8;
+
+        Rgb888::new(
+            mix(self.from.r(), self.to.r()),
+            mix(self.from.g(), self.to.g()),
+            mix(self.from.b(), self.to.b()),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RainbowEffect {
+    //ticks between shifting hue by one step
+    tempo: i32,
+    hue_offset: u8,
+    counter: i32,
+}
+
+impl RainbowEffect {
+    //hue change between neighbouring columns
+    const HUE_STEP: i32 = 4;
+
+    pub fn new(tempo: i32) -> Self {
+        RainbowEffect {
+            tempo,
+            hue_offset: 0,
+            counter: 0,
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.counter += 1;
+        if self.counter >= self.tempo {
+            self.counter = 0;
+            self.hue_offset = self.hue_offset.wrapping_add(Self::HUE_STEP as u8);
+        }
+    }
+
+    pub fn get(&self, x: i32) -> Rgb888 {
+        let hue = (x * Self::HUE_STEP - self.hue_offset as i32).rem_euclid(256);
+        hue_to_rgb(hue as u8)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PulseEffect {
+    //ticks between changing brightness by one step
+    tempo: i32,
+    level: u8,
+    rising: bool,
+    counter: i32,
+}
+
+impl PulseEffect {
+    const MIN_LEVEL: u8 = 32;
+    const LEVEL_STEP: u8 = 8;
+
+    pub fn new(tempo: i32) -> Self {
+        PulseEffect {
+            tempo,
+            level: 255,
+            rising: false,
+            counter: 0,
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.counter += 1;
+        if self.counter >= self.tempo {
+            self.counter = 0;
+
+            if self.rising {
+                self.level = self.level.saturating_add(Self::LEVEL_STEP);
+                if self.level == 255 {
+                    self.rising = false;
+                }
+            } else {
+                self.level = self.level.saturating_sub(Self::LEVEL_STEP);
+                if self.level <= Self::MIN_LEVEL {
+                    self.level = Self::MIN_LEVEL;
+                    self.rising = true;
+                }
+            }
+        }
+    }
+
+    pub fn get(&self, color: Rgb888) -> Rgb888 {
+        let scale = |c: u8| ((c as u16 * self.level as u16) / 255) as u8;
+        Rgb888::new(scale(color.r()), scale(color.g()), scale(color.b()))
+    }
+}
+
+/// Converts hue (full circle mapped to 0-255) to a fully saturated color
+fn hue_to_rgb(hue: u8) -> Rgb888 {
+    let sector = hue / 43;
+    let rising = ((hue - sector * 43) as u16 * 6) as u8;
+    let falling = 255 - rising;
+
+    match sector {
+        0 => Rgb888::new(255, rising, 0),
+        1 => Rgb888::new(falling, 255, 0),
+        2 => Rgb888::new(0, 255, rising),
+        3 => Rgb888::new(0, falling, 255),
+        4 => Rgb888::new(rising, 0, 255),
+        _ => Rgb888::new(255, 0, falling),
+    }
+}
+
+/// Draw target for glyphs, pixels that are On get the text color changed by the effect.
+/// Off pixels are left out, so the background drawn before the glyph stays
+pub struct ColorEffectTarget<'a, T: DrawTarget<Color = Rgb888>> {
+    target: &'a mut T,
+    effect: ColorEffect,
+    text_color: Rgb888,
+    width: i32,
+}
+
+impl<'a, T: DrawTarget<Color = Rgb888>> ColorEffectTarget<'a, T> {
+    pub fn new(target: &'a mut T, effect: ColorEffect, text_color: Rgb888, width: i32) -> Self {
+        ColorEffectTarget {
+            target,
+            effect,
+            text_color,
+            width,
+        }
+    }
+}
+
+impl<'a, T: DrawTarget<Color = Rgb888>> Dimensions for ColorEffectTarget<'a, T> {
+    fn bounding_box(&self) -> Rectangle {
+        self.target.bounding_box()
+    }
+}
+
+impl<'a, T: DrawTarget<Color = Rgb888>> DrawTarget for ColorEffectTarget<'a, T> {
+    type Color = BinaryColor;
+    type Error = T::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let effect = self.effect;
+        let text_color = self.text_color;
+        let width = self.width;
+
+        self.target.draw_iter(
+            pixels
+                .into_iter()
+                .filter(|Pixel(_, color)| color.is_on())
+                .map(|Pixel(point, _)| Pixel(point, effect.get(point.x, width, text_color))),
+        )
+    }
+}
@@ -1,7 +1,308 @@
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    image::{Image, ImageDrawableExt, ImageRaw},
+    mono_font::{ascii::FONT_6X9, mapping::GlyphMapping, DecorationDimensions, MonoFont},
+    pixelcolor::BinaryColor,
+    prelude::{OriginDimensions, Point, Size},
+    primitives::Rectangle,
+    Drawable,
+};
+#[cfg(feature = "large-fonts")]
+use embedded_graphics::mono_font::ascii::{FONT_10X20, FONT_9X15};
+use ibm437::IBM437_8X8_NORMAL;
+#[cfg(feature = "large-fonts")]
+use profont::PROFONT_18_POINT;
+use profont::PROFONT_7_POINT;
 
-#[derive(Debug, Clone, Copy)]
-pub enum Font{
+use super::{
+    charmap::{contains_char, CP437_HIGH},
+    proportional_font::{ProportionalFont, NARROW_6X9},
+    DisplayError,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Font {
     Default,
     ProFont,
     Ibm,
-}
\ No newline at end of file
+    #[cfg(feature = "large-fonts")]
+    Medium,
+    #[cfg(feature = "large-fonts")]
+    Large,
+    #[cfg(feature = "large-fonts")]
+    ProFontLarge,
+    Custom,
+    Narrow,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum FontFace {
+    Mono(&'static MonoFont<'static>),
+    Proportional(&'static ProportionalFont),
+}
+
+impl FontFace {
+    pub fn height(&self) -> u32 {
+        match self {
+            FontFace::Mono(font) => font.character_size.height,
+            FontFace::Proportional(font) => font.height,
+        }
+    }
+
+    pub fn baseline(&self) -> u32 {
+        match self {
+            FontFace::Mono(font) => font.baseline,
+            FontFace::Proportional(font) => font.baseline,
+        }
+    }
+
+    /// Width in pixels taken by c, including spacing
+    pub fn advance(&self, c: char) -> u32 {
+        match self {
+            FontFace::Mono(font) => font.character_size.width + font.character_spacing,
+            FontFace::Proportional(font) => font.advance(c),
+        }
+    }
+
+    /// Draws c's glyph with its left edge at position.x and baseline at position.y.
+    /// Pixels of the glyph are BinaryColor::On, the rest of its cell may be drawn as Off
+    pub fn draw_glyph<T: DrawTarget<Color = BinaryColor>>(
+        &self,
+        c: char,
+        position: Point,
+        target: &mut T,
+    ) -> Result<(), T::Error> {
+        match self {
+            FontFace::Mono(font) => {
+                //Glyphs are laid out in rows of the font's image
+                let size = font.character_size;
+                let glyphs_per_row = (font.image.size().width / size.width).max(1);
+                let index = font.glyph_mapping.index(c) as u32;
+                let corner = Point::new(
+                    (index % glyphs_per_row * size.width) as i32,
+                    (index / glyphs_per_row * size.height) as i32,
+                );
+
+                let glyph = font.image.sub_image(&Rectangle::new(corner, size));
+                let top_left = Point::new(position.x, position.y - font.baseline as i32);
+
+                Image::new(&glyph, top_left).draw(target)
+            }
+            FontFace::Proportional(font) => font.draw_char(c, position, target),
+        }
+    }
+}
+
+impl Font {
+    /// Names in the order of ids, Medium, Large and ProFontLarge need the large-fonts feature
+    pub const NAMES: [&'static str; 8] = [
+        "Default",
+        "ProFont",
+        "Ibm",
+        "Medium",
+        "Large",
+        "ProFontLarge",
+        "Custom",
+        "Narrow",
+    ];
+
+    pub fn from_id(id: u8) -> Option<Font> {
+        match id {
+            0 => Some(Font::Default),
+            1 => Some(Font::ProFont),
+            2 => Some(Font::Ibm),
+            #[cfg(feature = "large-fonts")]
+            3 => Some(Font::Medium),
+            #[cfg(feature = "large-fonts")]
+            4 => Some(Font::Large),
+            #[cfg(feature = "large-fonts")]
+            5 => Some(Font::ProFontLarge),
+            6 => Some(Font::Custom),
+            7 => Some(Font::Narrow),
+            _ => None,
+        }
+    }
+
+    pub fn id(&self) -> u8 {
+        match self {
+            Font::Default => 0,
+            Font::ProFont => 1,
+            Font::Ibm => 2,
+            #[cfg(feature = "large-fonts")]
+            Font::Medium => 3,
+            #[cfg(feature = "large-fonts")]
+            Font::Large => 4,
+            #[cfg(feature = "large-fonts")]
+            Font::ProFontLarge => 5,
+            Font::Custom => 6,
+            Font::Narrow => 7,
+        }
+    }
+
+    /// Returns None for Font::Custom if no font was uploaded
+    pub fn face(&self) -> Option<FontFace> {
+        match self {
+            Font::Narrow => Some(FontFace::Proportional(&NARROW_6X9)),
+            _ => self.mono_font().map(FontFace::Mono),
+        }
+    }
+
+    pub fn has_glyph(&self, c: char) -> bool {
+        let printable_ascii = (' '..='~').contains(&c);
+
+        match self {
+            Font::Ibm => printable_ascii || contains_char(CP437_HIGH, c),
+            Font::Custom => unsafe {
+                CUSTOM_FONT.is_some()
+                    && (c as u32).wrapping_sub(CUSTOM_GLYPH_MAPPING.first_char)
+                        < CUSTOM_GLYPH_MAPPING.char_count
+            },
+            _ => printable_ascii,
+        }
+    }
+
+    /// Returns None for proportional fonts and for Font::Custom if no font was uploaded
+    pub fn mono_font(&self) -> Option<&'static MonoFont<'static>> {
+        match self {
+            Font::Default => Some(&FONT_6X9),
+            Font::ProFont => Some(&PROFONT_7_POINT),
+            Font::Ibm => Some(&IBM437_8X8_NORMAL),
+            #[cfg(feature = "large-fonts")]
+            Font::Medium => Some(&FONT_9X15),
+            #[cfg(feature = "large-fonts")]
+            Font::Large => Some(&FONT_10X20),
+            #[cfg(feature = "large-fonts")]
+            Font::ProFontLarge => Some(&PROFONT_18_POINT),
+            Font::Custom => unsafe { CUSTOM_FONT.as_ref() },
+            Font::Narrow => None,
+        }
+    }
+}
+
+//Halved with bcm, which takes the RAM for more bitplanes
+#[cfg(not(feature = "bcm"))]
+pub const CUSTOM_FONT_DATA_SIZE: usize = 2048;
+#[cfg(feature = "bcm")]
+pub const CUSTOM_FONT_DATA_SIZE: usize = 1024;
+
+/// Parameters of an uploaded monospace font.
+/// Glyph bitmaps are expected to be placed side by side in a single strip,
+/// 1 bit per pixel, MSB first, with every pixel row padded to a whole byte.
+#[derive(Debug, Clone, Copy)]
+pub struct CustomFontHeader {
+    pub glyph_width: u8,
+    pub glyph_height: u8,
+    pub baseline: u8,
+    pub first_char: u32,
+    pub char_count: u16,
+}
+
+impl CustomFontHeader {
+    /// Glyphs have a size, the baseline is inside them and the strip fits the upload buffer
+    pub fn is_valid(&self) -> bool {
+        self.glyph_width != 0
+            && self.glyph_height != 0
+            && self.char_count != 0
+            && self.baseline < self.glyph_height
+            && self.data_len() <= CUSTOM_FONT_DATA_SIZE
+    }
+
+    fn image_width(&self) -> u32 {
+        self.glyph_width as u32 * self.char_count as u32
+    }
+
+    fn data_len(&self) -> usize {
+        ((self.image_width() as usize + 7) / 8) * self.glyph_height as usize
+    }
+}
+
+struct RangeGlyphMapping {
+    first_char: u32,
+    char_count: u32,
+}
+
+impl GlyphMapping for RangeGlyphMapping {
+    fn index(&self, c: char) -> usize {
+        let index = (c as u32).wrapping_sub(self.first_char);
+        if index < self.char_count {
+            return index as usize;
+        }
+
+        //Fallback to '?' if the font has it, to first glyph otherwise
+        let replacement = ('?' as u32).wrapping_sub(self.first_char);
+        if replacement < self.char_count {
+            replacement as usize
+        } else {
+            0
+        }
+    }
+}
+
+//Uploaded font lives in RAM, so TextDisplay styles can hold 'static references to it
+static mut CUSTOM_FONT_DATA: [u8; CUSTOM_FONT_DATA_SIZE] = [0; CUSTOM_FONT_DATA_SIZE];
+static mut CUSTOM_GLYPH_MAPPING: RangeGlyphMapping = RangeGlyphMapping {
+    first_char: 0,
+    char_count: 0,
+};
+//Upload in progress, taken by commit so data can't change a font rows are drawn with
+static mut CUSTOM_FONT_HEADER: Option<CustomFontHeader> = None;
+static mut CUSTOM_FONT: Option<MonoFont<'static>> = None;
+
+/// Starts a new upload, the previous custom font is discarded.
+/// Rows using Font::Custom have to be switched to another font before calling this.
+pub fn begin_custom_font(header: CustomFontHeader) -> Result<(), DisplayError> {
+    if !header.is_valid() {
+        return Err(DisplayError::InvalidSetting);
+    }
+
+    unsafe {
+        CUSTOM_FONT = None;
+        CUSTOM_FONT_DATA.fill(0);
+        CUSTOM_FONT_HEADER = Some(header);
+    }
+
+    Ok(())
+}
+
+pub fn write_custom_font(offset: usize, data: &[u8]) -> Result<(), DisplayError> {
+    let header = unsafe { CUSTOM_FONT_HEADER.ok_or(DisplayError::InvalidSetting)? };
+
+    if offset + data.len() > header.data_len() {
+        return Err(DisplayError::OutOfBounds);
+    }
+
+    unsafe {
+        CUSTOM_FONT_DATA[offset..offset + data.len()].copy_from_slice(data);
+    }
+
+    Ok(())
+}
+
+/// Makes the uploaded font available as Font::Custom, data is rejected until the next begin
+pub fn commit_custom_font() -> Result<(), DisplayError> {
+    let header = unsafe {
+        CUSTOM_FONT_HEADER
+            .take()
+            .ok_or(DisplayError::InvalidSetting)?
+    };
+    let height = header.glyph_height as u32;
+
+    unsafe {
+        CUSTOM_GLYPH_MAPPING = RangeGlyphMapping {
+            first_char: header.first_char,
+            char_count: header.char_count as u32,
+        };
+
+        CUSTOM_FONT = Some(MonoFont {
+            image: ImageRaw::new(&CUSTOM_FONT_DATA[..header.data_len()], header.image_width()),
+            glyph_mapping: &CUSTOM_GLYPH_MAPPING,
+            character_size: Size::new(header.glyph_width as u32, height),
+            character_spacing: 0,
+            baseline: header.baseline as u32,
+            underline: DecorationDimensions::new(header.baseline as u32 + 2, 1),
+            strikethrough: DecorationDimensions::new(height / 2, 1),
+        });
+    }
+
+    Ok(())
+}
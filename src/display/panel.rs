@@ -0,0 +1,95 @@
+use cortex_m::interrupt;
+use embedded_graphics::pixelcolor::Rgb888;
+use hub75::{ChainLayout, Geometry, Hub75, Orientation, OutputPort, Pins};
+
+use super::DisplayError;
+
+/// Panel settings that are not covered by DrawTarget
+pub trait Panel {
+    fn set_brightness(&mut self, brightness: u8);
+    fn brightness(&self) -> u8;
+    fn set_gamma(&mut self, table: &[u8; 256]);
+    fn set_white_balance(&mut self, r: u8, g: u8, b: u8);
+    fn set_geometry(&mut self, geometry: Geometry) -> Result<(), DisplayError>;
+    fn geometry(&self) -> Geometry;
+    fn set_layout(&mut self, layout: ChainLayout) -> Result<(), DisplayError>;
+    fn layout(&self) -> ChainLayout;
+    fn set_orientation(&mut self, orientation: Orientation);
+    fn orientation(&self) -> Orientation;
+    /// Bits per color channel
+    fn color_depth(&self) -> usize;
+    /// Framebuffer as raw color bits of every stored word, for saving and restoring it
+    fn color_words(&self) -> usize;
+    fn color_bits(&self, index: usize) -> u8;
+    fn set_color_bits(&mut self, index: usize, colors: u8);
+    /// Color of a pixel as drawn, with gamma correction and white balance reverted
+    fn pixel_color(&self, x: i32, y: i32) -> Option<Rgb888>;
+}
+
+//Scan-out interrupts read the panel while commands run in the main loop,
+//so settings are changed with interrupts disabled
+impl<PORT: OutputPort, const PIN_POS: Pins, const WORDS: usize> Panel
+    for Hub75<PORT, PIN_POS, WORDS>
+{
+    fn set_brightness(&mut self, brightness: u8) {
+        interrupt::free(|_| Hub75::set_brightness(self, brightness));
+    }
+
+    fn brightness(&self) -> u8 {
+        Hub75::brightness(self)
+    }
+
+    fn set_gamma(&mut self, table: &[u8; 256]) {
+        interrupt::free(|_| Hub75::set_gamma(self, table));
+    }
+
+    fn set_white_balance(&mut self, r: u8, g: u8, b: u8) {
+        interrupt::free(|_| Hub75::set_white_balance(self, r, g, b));
+    }
+
+    fn set_geometry(&mut self, geometry: Geometry) -> Result<(), DisplayError> {
+        interrupt::free(|_| Hub75::set_geometry(self, geometry))
+            .map_err(|_| DisplayError::InvalidSetting)
+    }
+
+    fn geometry(&self) -> Geometry {
+        Hub75::geometry(self)
+    }
+
+    fn set_layout(&mut self, layout: ChainLayout) -> Result<(), DisplayError> {
+        interrupt::free(|_| Hub75::set_layout(self, layout))
+            .map_err(|_| DisplayError::InvalidSetting)
+    }
+
+    fn layout(&self) -> ChainLayout {
+        Hub75::layout(self)
+    }
+
+    fn set_orientation(&mut self, orientation: Orientation) {
+        interrupt::free(|_| Hub75::set_orientation(self, orientation));
+    }
+
+    fn orientation(&self) -> Orientation {
+        Hub75::orientation(self)
+    }
+
+    fn color_depth(&self) -> usize {
+        Hub75::color_depth(self)
+    }
+
+    fn color_words(&self) -> usize {
+        Hub75::color_words(self)
+    }
+
+    fn color_bits(&self, index: usize) -> u8 {
+        Hub75::color_bits(self, index)
+    }
+
+    fn set_color_bits(&mut self, index: usize, colors: u8) {
+        Hub75::set_color_bits(self, index, colors);
+    }
+
+    fn pixel_color(&self, x: i32, y: i32) -> Option<Rgb888> {
+        Hub75::pixel_color(self, x, y)
+    }
+}
@@ -6,6 +6,9 @@ pub enum TextAnimation {
 }
 
 impl TextAnimation {
+    /// Names in the order of ids used by decode
+    pub const NAMES: [&'static str; 3] = ["None", "Blinking", "Slide"];
+
     pub fn tick(&mut self) {
         match self {
             TextAnimation::SlideAnimation(anim) => anim.tick(),
@@ -25,6 +28,39 @@ impl TextAnimation {
             },
         }
     }
+
+    /// Kind, tempo and direction, as in SetAnimation command
+    pub fn encode(&self) -> [u8; 3] {
+        match self {
+            TextAnimation::NoAnimation => [0, 0, 0],
+            TextAnimation::BlinkingAnimation(anim) => [1, anim.tempo as u8, 0],
+            TextAnimation::SlideAnimation(anim) => {
+                let direction = match anim.direction {
+                    SlideDirection::Right => 1,
+                    SlideDirection::Left => 0,
+                };
+                [2, anim.tempo as u8, direction]
+            }
+        }
+    }
+
+    /// Restarts the animation from settings returned by encode
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let animation = match data[0] {
+            0 => TextAnimation::NoAnimation,
+            1 => TextAnimation::BlinkingAnimation(BlinkingAnimation::new(data[1] as i32)),
+            2 => {
+                let direction = match data[2] {
+                    1 => SlideDirection::Right,
+                    _ => SlideDirection::Left,
+                };
+                TextAnimation::SlideAnimation(SlideAnimation::new(data[1] as i32, direction))
+            }
+            _ => return None,
+        };
+
+        Some(animation)
+    }
 }
 
 pub struct AnimationState {
@@ -48,6 +84,8 @@ pub struct SlideAnimation {
     pub tempo: i32,
     x_offset: i32,
     counter: i32,
+    //width of the display, text enters from its edge
+    screen_width: i32,
 }
 
 impl SlideAnimation {
@@ -58,6 +96,7 @@ impl SlideAnimation {
             tempo,
             slide_length: 0,
             direction,
+            screen_width: 64,
         }
     }
 
@@ -76,19 +115,20 @@ impl SlideAnimation {
                 SlideDirection::Left => {
                     self.x_offset -= 1;
                     if self.x_offset.abs() > self.slide_length as i32 {
-                        self.x_offset = 64;
+                        self.x_offset = self.screen_width;
                     }
                 }
             }
         }
     }
 
-    pub fn set_length(&mut self, mut length: usize){
-        if length < 80{
-            length = 80;
+    pub fn set_length(&mut self, mut length: usize, screen_width: usize){
+        if length < screen_width + 16{
+            length = screen_width + 16;
         }
 
         self.slide_length = length;
+        self.screen_width = screen_width as i32;
     }
 
     pub fn get(&mut self) -> AnimationState {
@@ -1,47 +1,65 @@
-use crate::SlideAnimation;
+use core::{fmt::Write as _, ops::Range};
 use heapless::String;
 
 use embedded_graphics::{
     draw_target::DrawTarget,
     mono_font::{MonoTextStyle, MonoTextStyleBuilder},
     pixelcolor::{Rgb888, RgbColor},
-    prelude::Point,
-    text::Text,
-    Drawable,
+    prelude::{Point, Size},
+    primitives::Rectangle,
 };
 
 use embedded_graphics::mono_font::ascii::FONT_6X9;
-use ibm437::IBM437_8X8_NORMAL;
-use profont::PROFONT_7_POINT;
 
-use super::{font::Font, text_animations::TextAnimation, DisplayError};
+use umx_core::saved_state::SavedRow;
+
+use crate::storage::{RecordReader, RecordWriter};
+
+use super::{
+    charmap::{contains_char, map_char, REPLACEMENT_CHAR},
+    color_effects::{ColorEffect, ColorEffectTarget},
+    font::{Font, FontFace},
+    text_animations::{AnimationState, TextAnimation},
+    DisplayError,
+};
 
 const ROWS: usize = 3;
-const OFFSET: i32 = 8;
+const TOP_MARGIN: i32 = 2;
+//Rows are at least as tall as the default font, so rows using it stay 9 px apart
+const MIN_ROW_HEIGHT: u32 = 9;
 const LETTER_WIDTH: usize = 9;
+//Canvas width assumed until the first update
 const ROW_PX_WIDTH: usize = 64;
-
-pub fn utf8_slice(s: &str, start: usize, end: usize) -> Option<&str> {
-    let mut iter = s
-        .char_indices()
-        .map(|(pos, _)| pos)
-        .chain(Some(s.len()))
-        .skip(start)
-        .peekable();
-    let start_pos = *iter.peek()?;
-    for _ in start..end {
-        iter.next();
-    }
-    Some(&s[start_pos..*iter.peek()?])
+const UNMAPPED_LENGTH: usize = 32;
+
+/// Colors a row's glyphs are drawn with, the effect recolors only the text pixels
+#[derive(Debug, Clone, Copy)]
+struct GlyphColors {
+    text: Rgb888,
+    background: Option<Rgb888>,
+    effect: ColorEffect,
 }
+
 #[derive(Debug)]
 pub struct TextDisplay<'a, const TEXT_ROW_LENGTH: usize> {
+    //text as written, mapped to the font's glyphs when drawn
     rows: [String<TEXT_ROW_LENGTH>; ROWS],
     animation: [TextAnimation; ROWS],
+    color_effect: [ColorEffect; ROWS],
+    font: [Font; ROWS],
+    face: [FontFace; ROWS],
+    unmapped: [String<UNMAPPED_LENGTH>; ROWS],
     style: [MonoTextStyle<'a, Rgb888>; ROWS],
+    //width of the target, rows span all chained panels
+    row_width: usize,
+    //animations stop and nothing is drawn, e.g. during a screenshot
+    frozen: bool,
+    //where the text of each row was drawn in the last frame, None if it was hidden
+    drawn: [Option<Point>; ROWS],
 }
 
 impl<'a, const TEXT_ROW_LENGTH: usize> TextDisplay<'a, TEXT_ROW_LENGTH> {
+    #[inline(always)]
     pub fn new() -> Self {
         let style = MonoTextStyleBuilder::new()
             .text_color(Rgb888::new(255, 255, 255))
@@ -56,7 +74,14 @@ impl<'a, const TEXT_ROW_LENGTH: usize> TextDisplay<'a, TEXT_ROW_LENGTH> {
                 TextAnimation::NoAnimation,
                 TextAnimation::NoAnimation,
             ],
+            color_effect: [ColorEffect::NoEffect; ROWS],
+            font: [Font::Default; ROWS],
+            face: [FontFace::Mono(&FONT_6X9); ROWS],
+            unmapped: [String::new(), String::new(), String::new()],
             style: [style; 3],
+            row_width: ROW_PX_WIDTH,
+            frozen: false,
+            drawn: [None; ROWS],
         }
     }
 
@@ -65,11 +90,10 @@ impl<'a, const TEXT_ROW_LENGTH: usize> TextDisplay<'a, TEXT_ROW_LENGTH> {
             return Err(DisplayError::OutOfBounds);
         }
 
-        if let TextAnimation::SlideAnimation(ref mut anim) = &mut self.animation[row] {
-            update_slide_length(anim, text.len())
-        }
-
         self.rows[row] = text;
+        self.drawn[row] = None;
+        self.collect_unmapped(row);
+        self.update_slide_length(row);
 
         Ok(())
     }
@@ -88,178 +112,423 @@ impl<'a, const TEXT_ROW_LENGTH: usize> TextDisplay<'a, TEXT_ROW_LENGTH> {
         Ok(())
     }
 
+    pub fn set_background(
+        &mut self,
+        row: usize,
+        rgb_color: Option<(u8, u8, u8)>,
+    ) -> Result<(), DisplayError> {
+        if row >= ROWS {
+            return Err(DisplayError::OutOfBounds);
+        }
+
+        //None means transparent background
+        self.style[row].background_color = rgb_color.map(|(r, g, b)| Rgb888::new(r, g, b));
+        self.drawn[row] = None;
+
+        Ok(())
+    }
+
     pub fn set_font(&mut self, row: usize, font: Font) -> Result<(), DisplayError> {
         if row >= ROWS {
             return Err(DisplayError::OutOfBounds);
         }
 
-        let style = &mut self.style[row];
+        let face = font.face().ok_or(DisplayError::InvalidSetting)?;
 
-        match font {
-            Font::Default => style.font = &FONT_6X9,
-            Font::Ibm => style.font = &IBM437_8X8_NORMAL,
-            Font::ProFont => style.font = &PROFONT_7_POINT,
-        };
+        if let FontFace::Mono(mono_font) = face {
+            self.style[row].font = mono_font;
+        }
+
+        self.face[row] = face;
+        self.font[row] = font;
+        self.drawn[row] = None;
+        self.collect_unmapped(row);
+        self.update_slide_length(row);
 
         Ok(())
     }
 
+    /// Characters of the row's text that the row's font can't display
+    pub fn unmapped(&self, row: usize) -> Result<&str, DisplayError> {
+        if row >= ROWS {
+            return Err(DisplayError::OutOfBounds);
+        }
+
+        Ok(&self.unmapped[row])
+    }
+
+    /// Writes font id, colors, animation, effect and text of the row,
+    /// e.g. "Font:0;Color:255,255,255;Background:None;Animation:0,0,0;Effect:0,0,0,0,0,0,0;Text:A".
+    /// Animation and effect are encoded as in SetAnimation and SetColorEffect,
+    /// text is cut off if it doesn't fit
+    pub fn describe_row<const LENGTH: usize>(
+        &self,
+        row: usize,
+        text: &mut String<LENGTH>,
+    ) -> Result<(), DisplayError> {
+        if row >= ROWS {
+            return Err(DisplayError::OutOfBounds);
+        }
+
+        let style = &self.style[row];
+        let color = style.text_color.unwrap_or(Rgb888::WHITE);
+
+        write!(
+            text,
+            "Font:{};Color:{},{},{};Background:",
+            self.font[row].id(),
+            color.r(),
+            color.g(),
+            color.b()
+        )
+        .ok();
+
+        match style.background_color {
+            Some(color) => write!(text, "{},{},{}", color.r(), color.g(), color.b()),
+            None => write!(text, "None"),
+        }
+        .ok();
+
+        write_values(text, ";Animation:", &self.animation[row].encode());
+        write_values(text, ";Effect:", &self.color_effect[row].encode());
+
+        text.push_str(";Text:").ok();
+        for c in self.rows[row].chars() {
+            if text.push(c).is_err() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Switches rows using the custom font back to the default one,
+    /// so the custom font can be safely replaced
+    pub fn release_custom_font(&mut self) {
+        for row in 0..ROWS {
+            if self.font[row] == Font::Custom {
+                self.set_font(row, Font::Default).ok();
+            }
+        }
+    }
+
     pub fn set_animation(
         &mut self,
         row: usize,
-        mut animation: TextAnimation,
+        animation: TextAnimation,
     ) -> Result<(), DisplayError> {
         if row >= ROWS {
             return Err(DisplayError::OutOfBounds);
         }
 
-        if let TextAnimation::SlideAnimation(ref mut anim) = animation {
-            update_slide_length(anim, self.rows[row].len())
+        self.animation[row] = animation;
+        self.drawn[row] = None;
+        self.update_slide_length(row);
+
+        Ok(())
+    }
+
+    pub fn set_color_effect(
+        &mut self,
+        row: usize,
+        color_effect: ColorEffect,
+    ) -> Result<(), DisplayError> {
+        if row >= ROWS {
+            return Err(DisplayError::OutOfBounds);
         }
 
-        self.animation[row] = animation;
+        self.color_effect[row] = color_effect;
 
         Ok(())
     }
 
+    /// Keeps the last drawn frame on the target until unfrozen
+    pub fn set_frozen(&mut self, frozen: bool) {
+        self.frozen = frozen;
+    }
+
     pub fn update<T: DrawTarget<Color = Rgb888>>(&mut self, target: &mut T) {
+        if self.frozen {
+            return;
+        }
+
+        let row_width = target.bounding_box().size.width as usize;
+        if row_width != self.row_width {
+            self.row_width = row_width;
+            for row in 0..ROWS {
+                self.update_slide_length(row);
+            }
+        }
+
         for i in 0..ROWS {
             let anim_state = self.animation[i].get();
+            let position = self.text_position(i, &anim_state);
+
+            if self.style[i].background_color.is_none() {
+                self.erase_text(i, position, target);
+            }
+
+            self.draw_row(i, &anim_state, target);
 
-            if anim_state.visible {
-                match self.animation[i] {
-                    TextAnimation::SlideAnimation(_anim) => {
-                        let mut string = String::<512>::from(" ");
-
-                        let anim_offset = anim_state.x_offset;
-                        let glyph_width = self.style[i].font.character_size.width;
-
-                        if anim_offset > 0 {
-                            let pixels_still_on_screen = ROW_PX_WIDTH as i32 - anim_offset;
-                            if pixels_still_on_screen > 0 {
-                                let mut characters_still_on_screen =
-                                    (pixels_still_on_screen as usize / glyph_width as usize) + 1;
-
-                                let char_length = self.rows[i].chars().count();
-
-                                if characters_still_on_screen >= char_length {
-                                    characters_still_on_screen = char_length - 1;
-                                }
-
-                                string
-                                    .push_str(
-                                        utf8_slice(
-                                            &self.rows[i],
-                                            0,
-                                            characters_still_on_screen + 1,
-                                        )
-                                        .unwrap(),
-                                    )
-                                    .ok();
-                            }
-
-                            string.push_str(" ").ok();
-
-                            Text::new(
-                                string.as_str(),
-                                Point::new(
-                                    anim_offset,
-                                    OFFSET + (i as i32 * 9) + anim_state.y_offset,
-                                ),
-                                self.style[i],
-                            )
-                            .draw(target)
-                            .ok();
-                        } else {
-                            let characters_skipped =
-                                anim_offset.abs() as usize / glyph_width as usize;
-
-                            let x_offset = anim_offset % glyph_width as i32;
-
-                            if characters_skipped < self.rows[i].len() {
-                                let characters_that_will_fit =
-                                    (ROW_PX_WIDTH / glyph_width as usize) + 1;
-
-                                let mut last_index = characters_skipped + characters_that_will_fit;
-
-                                let char_length = self.rows[i].chars().count();
-
-                                if last_index >= char_length {
-                                    last_index = char_length - 1;
-                                }
-
-                                if last_index >= characters_skipped {
-                                    string
-                                        .push_str(
-                                            utf8_slice(
-                                                &self.rows[i],
-                                                characters_skipped,
-                                                last_index + 1,
-                                            )
-                                            .unwrap_or_else(|| {
-                                                panic!(
-                                                    "b: {}, e:{}",
-                                                    characters_skipped, last_index
-                                                )
-                                            }),
-                                        )
-                                        .ok();
-                                }
-                            }
-
-                            string.push_str(" ").ok();
-
-                            Text::new(
-                                string.as_str(),
-                                Point::new(x_offset, OFFSET + (i as i32 * 9) + anim_state.y_offset),
-                                self.style[i],
-                            )
-                            .draw(target)
-                            .ok();
-                        }
-                    }
-                    _ => {
-                        let glyph_width = self.style[i].font.character_size.width;
-                        let mut last_index = (ROW_PX_WIDTH / glyph_width as usize) + 1;
-
-                        let char_length = self.rows[i].chars().count();
-
-                        if last_index >= char_length {
-                            last_index = char_length - 1;
-                        }
-
-                        Text::new(
-                            utf8_slice(&self.rows[i], 0, last_index + 1).unwrap(),
-                            Point::new(0, OFFSET + (i as i32 * 9)),
-                            self.style[i],
-                        )
-                        .draw(target)
-                        .ok();
-                    }
+            self.drawn[i] = position;
+        }
+    }
+
+    /// Top left corner of the row's text at its baseline, None while the text is hidden
+    fn text_position(&self, row: usize, anim_state: &AnimationState) -> Option<Point> {
+        if !anim_state.visible {
+            return None;
+        }
+
+        let baseline = self.baseline(row) + anim_state.y_offset;
+        Some(Point::new(anim_state.x_offset, baseline))
+    }
+
+    /// Rows are stacked below each other, each as tall as its font
+    fn baseline(&self, row: usize) -> i32 {
+        let top: u32 = self.face[..row]
+            .iter()
+            .map(|face| face.height().max(MIN_ROW_HEIGHT))
+            .sum();
+
+        TOP_MARGIN + top as i32 + self.face[row].baseline() as i32
+    }
+
+    /// Transparent rows don't paint over the previous frame, so the glyphs it drew
+    /// at another position are erased, leaving the pixels around them untouched.
+    /// Commands changing a row clear the whole target, so the text drawn is always the current one
+    fn erase_text<T: DrawTarget<Color = Rgb888>>(
+        &self,
+        row: usize,
+        position: Option<Point>,
+        target: &mut T,
+    ) {
+        if let Some(drawn) = self.drawn[row] {
+            if position != Some(drawn) {
+                let colors = GlyphColors {
+                    text: Rgb888::BLACK,
+                    background: None,
+                    effect: ColorEffect::NoEffect,
+                };
+                self.draw_text(row, drawn, colors, target);
+            }
+        }
+    }
+
+    fn draw_row<T: DrawTarget<Color = Rgb888>>(
+        &self,
+        i: usize,
+        anim_state: &AnimationState,
+        target: &mut T,
+    ) {
+        let background = self.style[i].background_color;
+        let row_width = self.row_width as i32;
+
+        let position = match self.text_position(i, anim_state) {
+            Some(position) => position,
+            None => {
+                if let Some(background) = background {
+                    self.fill_row(i, anim_state.y_offset, 0..row_width, background, target);
                 }
-            } else {
-                //Because clearing the display would take too much time ;)
-                Text::new(
-                    "                ",
-                    Point::new(
-                        anim_state.x_offset,
-                        OFFSET + (i as i32 * 9) + anim_state.y_offset,
-                    ),
-                    self.style[i],
-                )
-                .draw(target)
-                .ok();
+                return;
             }
+        };
+
+        let colors = GlyphColors {
+            text: self.style[i].text_color.unwrap_or(Rgb888::WHITE),
+            background,
+            effect: self.color_effect[i],
+        };
+        let end = self.draw_text(i, position, colors, target);
+
+        //Paint over leftovers from the previous frame on both sides of the text
+        if let Some(background) = background {
+            self.fill_row(i, anim_state.y_offset, 0..position.x, background, target);
+            self.fill_row(i, anim_state.y_offset, end..row_width, background, target);
         }
     }
 
+    /// Draws the visible part of the row's text, returns x right after the last glyph
+    fn draw_text<T: DrawTarget<Color = Rgb888>>(
+        &self,
+        row: usize,
+        position: Point,
+        colors: GlyphColors,
+        target: &mut T,
+    ) -> i32 {
+        let row_width = self.row_width as i32;
+        let mut x = position.x;
+
+        for c in self.glyphs(row) {
+            let advance = self.face[row].advance(c) as i32;
+
+            if x >= row_width {
+                break;
+            }
+
+            //Skip glyphs that already slid out of the screen
+            if x + advance > 0 {
+                let glyph_position = Point::new(x, position.y);
+                self.draw_char(row, c, glyph_position, colors, target);
+            }
+
+            x += advance;
+        }
+
+        x
+    }
+
+    fn draw_char<T: DrawTarget<Color = Rgb888>>(
+        &self,
+        row: usize,
+        c: char,
+        position: Point,
+        colors: GlyphColors,
+        target: &mut T,
+    ) {
+        let face = self.face[row];
+
+        //Background goes first, so the effect below only sees the glyph's own pixels
+        if let Some(background) = colors.background {
+            let cell = Rectangle::new(
+                Point::new(position.x, position.y - face.baseline() as i32),
+                Size::new(face.advance(c), face.height()),
+            );
+            target.fill_solid(&cell, background).ok();
+        }
+
+        let mut foreground =
+            ColorEffectTarget::new(target, colors.effect, colors.text, self.row_width as i32);
+        face.draw_glyph(c, position, &mut foreground).ok();
+    }
+
+    fn fill_row<T: DrawTarget<Color = Rgb888>>(
+        &self,
+        row: usize,
+        y_offset: i32,
+        columns: Range<i32>,
+        color: Rgb888,
+        target: &mut T,
+    ) {
+        let start = columns.start.max(0);
+        let end = columns.end.min(self.row_width as i32);
+
+        if start >= end {
+            return;
+        }
+
+        let face = self.face[row];
+        let baseline = self.baseline(row) + y_offset;
+
+        let area = Rectangle::new(
+            Point::new(start, baseline - face.baseline() as i32),
+            Size::new((end - start) as u32, face.height()),
+        );
+        target.fill_solid(&area, color).ok();
+    }
+
+    /// Text of the row with characters missing from the row's font replaced
+    /// by the closest available ones
+    fn glyphs(&self, row: usize) -> impl Iterator<Item = char> + '_ {
+        let font = self.font[row];
+
+        self.rows[row]
+            .chars()
+            .map(move |c| map_char(font, c).unwrap_or(REPLACEMENT_CHAR))
+    }
+
+    fn collect_unmapped(&mut self, row: usize) {
+        let font = self.font[row];
+        let unmapped = &mut self.unmapped[row];
+        unmapped.clear();
+
+        for c in self.rows[row].chars() {
+            if map_char(font, c).is_none() && !contains_char(unmapped, c) {
+                unmapped.push(c).ok();
+            }
+        }
+    }
+
+    /// Width of the row's text in pixels
+    fn text_width(&self, row: usize) -> usize {
+        self.glyphs(row)
+            .map(|c| self.face[row].advance(c) as usize)
+            .sum()
+    }
+
+    fn update_slide_length(&mut self, row: usize) {
+        let text_width = self.text_width(row);
+
+        if let TextAnimation::SlideAnimation(ref mut anim) = &mut self.animation[row] {
+            anim.set_length(text_width + 2 * LETTER_WIDTH, self.row_width);
+        }
+    }
+
+    /// Stores text, font, colors and effects of every row.
+    /// Animations and effects start over when restored
+    pub fn save(&self, writer: &mut RecordWriter) -> Result<(), DisplayError> {
+        for row in 0..ROWS {
+            let style = &self.style[row];
+            let text_color = style.text_color.unwrap_or(Rgb888::WHITE);
+
+            SavedRow {
+                font: self.font[row].id(),
+                text_color: [text_color.r(), text_color.g(), text_color.b()],
+                background: style
+                    .background_color
+                    .map(|color| [color.r(), color.g(), color.b()]),
+                animation: self.animation[row].encode(),
+                color_effect: self.color_effect[row].encode(),
+                text: &self.rows[row],
+            }
+            .save(writer)?;
+        }
+
+        Ok(())
+    }
+
+    /// Fills an empty display with the rows saved by save.
+    /// Returns None if saved data is incomplete or doesn't fit
+    pub fn restore(&mut self, reader: &mut RecordReader) -> Option<()> {
+        for row in 0..ROWS {
+            let saved = SavedRow::restore(reader, TEXT_ROW_LENGTH)?;
+            let [r, g, b] = saved.text_color;
+
+            //Custom font is not saved, rows using it fall back to the default one
+            self.set_font(row, Font::from_id(saved.font)?).ok();
+            self.set_color(row, (r, g, b)).ok()?;
+            let background = saved.background.map(|[r, g, b]| (r, g, b));
+            self.set_background(row, background).ok()?;
+            self.set_animation(row, TextAnimation::decode(&saved.animation)?)
+                .ok()?;
+            self.set_color_effect(row, ColorEffect::decode(&saved.color_effect)?)
+                .ok()?;
+            self.write(row, String::from(saved.text)).ok()?;
+        }
+
+        Some(())
+    }
+
     pub fn anim_tick(&mut self) {
+        if self.frozen {
+            return;
+        }
+
         for i in 0..ROWS {
             self.animation[i].tick();
+            self.color_effect[i].tick();
         }
     }
 }
 
-fn update_slide_length(anim: &mut SlideAnimation, text_len: usize) {
-    anim.set_length((text_len + 2) * LETTER_WIDTH);
+//Comma separated values after the label
+fn write_values<const LENGTH: usize>(text: &mut String<LENGTH>, label: &str, values: &[u8]) {
+    text.push_str(label).ok();
+
+    for (i, value) in values.iter().enumerate() {
+        if i > 0 {
+            text.push(',').ok();
+        }
+        write!(text, "{}", value).ok();
+    }
 }
@@ -0,0 +1,25 @@
+pub use umx_core::link::{Link, LinkPriority};
+
+use umx_core::link::LinkHold;
+
+use crate::config;
+
+//How long the priority link keeps the other one out after its last command
+const HOLD_SECONDS: u32 = 2;
+
+static HOLD: LinkHold = LinkHold::new();
+
+/// Returns false if the command has to be refused because the other link has priority.
+/// Every accepted command of the priority link extends its hold
+pub fn accept(link: Link) -> bool {
+    let config = config::current();
+    //anim rate is at least 1 Hz
+    let ticks = (HOLD_SECONDS * config.anim_rate as u32).min(u16::MAX as u32);
+
+    HOLD.accept(config.link_priority, link, ticks as u16)
+}
+
+/// Counts down the hold of the priority link, called on every anim tick
+pub fn tick() {
+    HOLD.tick();
+}
@@ -1,53 +1,68 @@
 #![no_std]
 #![no_main]
 #![feature(const_generics)]
+//Crate is named after the project
+#![allow(non_snake_case)]
 
+mod boot_screen;
 mod command_interpreter;
+mod config;
 mod crc;
 mod display;
+mod link;
+mod saved_state;
+mod storage;
 mod uart;
 
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::{
+    mem::MaybeUninit,
+    sync::atomic::{AtomicBool, AtomicU16, Ordering},
+};
 
 use crate::{
-    command_interpreter::interpret_command,
-    display::{
-        font::Font,
-        text_animations::{BlinkingAnimation, SlideAnimation, SlideDirection, TextAnimation},
-        text_display::TextDisplay,
-        DisplayMode,
-    },
-    uart::UartController,
+    command_interpreter::{interpret_command, Response},
+    config::FRAMEBUFFER_WORDS,
+    display::{brightness_schedule::BrightnessSchedule, panel::Panel, DisplayError, DisplayMode},
+    link::Link,
+    uart::{Frame, UartController, RX_BUFFER_SIZE},
 };
 
 use cortex_m::{asm::delay, peripheral::NVIC};
 use cortex_m_rt::entry;
 
-use crc::crc8_ccitt;
 use embedded_hal::digital::v2::OutputPin;
-use nb::block;
+#[cfg(not(feature = "bcm"))]
+use stm32f1xx_hal::delay::Delay;
+#[cfg(not(feature = "dma"))]
+use stm32f1xx_hal::pac::TIM2;
 use stm32f1xx_hal::{
-    delay::Delay,
-    pac::{interrupt, Interrupt, Peripherals, TIM2, TIM3, USART1},
+    pac::{interrupt, Interrupt, Peripherals, TIM3, USART1},
     prelude::*,
-    rcc::{Enable, Reset},
     serial::{Config, Rx, Serial, Tx},
     timer::{CountDownTimer, Event, Timer},
     usb::{Peripheral, UsbBus, UsbBusType},
 };
 
-use heapless::String;
-
-use usb_device::{bus::UsbBusAllocator, prelude::*};
+use usb_device::{bus::UsbBusAllocator, prelude::*, UsbError};
 use usbd_serial::{SerialPort, USB_CLASS_CDC};
+use umx_tx::TxQueue;
 
-use hub75::{Hub75, Pins};
+use hub75::{Hub75, Pins, RawPort};
 
+#[cfg(feature = "panic-semihosting")]
 extern crate panic_semihosting;
 
-static mut CLEAR_FLAG: AtomicBool = AtomicBool::new(false);
+//Formatting the panic message takes over 10 KB of flash, so by default a panic just stops
+//the firmware. Build with panic-semihosting feature to see the message in the debugger
+#[cfg(not(feature = "panic-semihosting"))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {
+        core::sync::atomic::compiler_fence(Ordering::SeqCst);
+    }
+}
 
-const DOUBLE_SCREEN_WIDTH: usize = 128;
+static mut CLEAR_FLAG: AtomicBool = AtomicBool::new(false);
 
 const PIN_POS: Pins = Pins {
     r1: 0,
@@ -59,6 +74,8 @@ const PIN_POS: Pins = Pins {
     a: 9,
     b: 10,
     c: 11,
+    d: 15,
+    e: 2,
     clock: 12,
     latch: 13,
     oe: 14,
@@ -67,23 +84,52 @@ const PIN_POS: Pins = Pins {
 static mut SERIAL_TX: Option<Tx<USART1>> = None;
 static mut SERIAL_RX: Option<Rx<USART1>> = None;
 
-static mut UARTCONTROLLER: Option<UartController<512>> = None;
+//Each link has its own receive state, so frames arriving at the same time don't mix.
+//A received frame waits there until the main loop executes it
+static mut UART_RX: UartController<RX_BUFFER_SIZE> = UartController::new();
+static mut USB_RX: UartController<RX_BUFFER_SIZE> = UartController::new();
+
+//Framed responses sent byte by byte from the transmit interrupt, fits one of maximum length
+static mut UART_TX: TxQueue<400> = TxQueue::new();
 
-static mut DISPLAY: Option<Hub75<PIN_POS, DOUBLE_SCREEN_WIDTH>> = None;
+type Display = Hub75<RawPort, PIN_POS, FRAMEBUFFER_WORDS>;
+//Uninitialized, so the framebuffer goes to .bss instead of taking flash for its initial value
+static mut DISPLAY_STORAGE: MaybeUninit<Display> = MaybeUninit::uninit();
+static mut DISPLAY: Option<&'static mut Display> = None;
 static mut DISPLAY_MODE: DisplayMode<256> = DisplayMode::DirectMode;
+#[cfg(not(feature = "bcm"))]
 static mut DELAY: Option<Delay> = None;
+#[cfg(not(feature = "dma"))]
 static mut DRAW_TIMER: Option<CountDownTimer<TIM2>> = None;
 static mut ANIM_TIMER: Option<CountDownTimer<TIM3>> = None;
 static mut OUTPUT_ENABLED: bool = true;
+//Anim ticks counted by TIM3, animations advance in the main loop
+static ANIM_TICKS: AtomicU16 = AtomicU16::new(0);
+
+//ANIM_TIMER ticks the schedule at 60 Hz
+static mut BRIGHTNESS_SCHEDULE: BrightnessSchedule = BrightnessSchedule::new(60 * 60);
 
 static mut USB_BUS: Option<UsbBusAllocator<UsbBusType>> = None;
-static mut USB_SERIAL: Option<usbd_serial::SerialPort<UsbBusType>> = None;
-static mut USB_DEVICE: Option<UsbDevice<UsbBusType>> = None;
+//Buffers of one packet, received bytes are taken right away and responses wait in USB_TX
+type UsbSerial = SerialPort<'static, UsbBusType, [u8; USB_PACKET_SIZE], [u8; USB_PACKET_SIZE]>;
+//Uninitialized as DISPLAY_STORAGE, an Option holding None would take flash for its initial value
+static mut USB_SERIAL_STORAGE: MaybeUninit<UsbSerial> = MaybeUninit::uninit();
+static mut USB_SERIAL: Option<&'static mut UsbSerial> = None;
+static mut USB_DEVICE_STORAGE: MaybeUninit<UsbDevice<UsbBusType>> = MaybeUninit::uninit();
+static mut USB_DEVICE: Option<&'static mut UsbDevice<UsbBusType>> = None;
+//Framed responses waiting until the host reads them, fits one of maximum length
+static mut USB_TX: TxQueue<400> = TxQueue::new();
+
+const USB_PACKET_SIZE: usize = 64;
+
+//Period of the bcm timer while there is nothing to show
+#[cfg(all(feature = "bcm", not(feature = "dma")))]
+const BCM_IDLE_US: u32 = 1000;
 
 #[entry]
 fn main() -> ! {
     let mut p = cortex_m::Peripherals::take().unwrap();
-    let mut dp = Peripherals::take().unwrap();
+    let dp = Peripherals::take().unwrap();
 
     let mut flash = dp.FLASH.constrain();
     let mut rcc = dp.RCC.constrain();
@@ -98,6 +144,10 @@ fn main() -> ! {
         .pclk1(24.mhz())
         .freeze(&mut flash.acr);
 
+    storage::init(flash);
+    config::init();
+    let device_config = config::current();
+
     let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
     let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
     let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
@@ -112,6 +162,8 @@ fn main() -> ! {
     let mut _a = gpiob.pb9.into_push_pull_output(&mut gpiob.crh);
     let mut _b = gpiob.pb10.into_push_pull_output(&mut gpiob.crh);
     let mut _c = gpiob.pb11.into_push_pull_output(&mut gpiob.crh);
+    let mut _d = gpiob.pb15.into_push_pull_output(&mut gpiob.crh);
+    let mut _e = gpiob.pb2.into_push_pull_output(&mut gpiob.crl);
     let mut _clock = gpiob.pb12.into_push_pull_output(&mut gpiob.crh);
     let mut _latch = gpiob.pb13.into_push_pull_output(&mut gpiob.crh);
     let mut _oe = gpiob.pb14.into_push_pull_output(&mut gpiob.crh);
@@ -126,7 +178,7 @@ fn main() -> ! {
         dp.USART1,
         (tx, rx),
         &mut afio.mapr,
-        Config::default().baudrate(115200.bps()),
+        Config::default().baudrate(device_config.baud_rate.bps()),
         clocks,
         &mut rcc.apb2,
     );
@@ -136,7 +188,6 @@ fn main() -> ! {
     rx.listen();
 
     unsafe {
-        UARTCONTROLLER = Some(UartController::new());
         SERIAL_TX = Some(tx);
         SERIAL_RX = Some(rx);
     }
@@ -161,19 +212,30 @@ fn main() -> ! {
     // Unsafe to allow access to static variables
     unsafe {
         USB_BUS = Some(bus);
-        USB_SERIAL = Some(SerialPort::new(USB_BUS.as_ref().unwrap()));
-        let usb_dev = UsbDeviceBuilder::new(USB_BUS.as_ref().unwrap(), UsbVidPid(0x16c0, 0x27dd))
+        USB_SERIAL_STORAGE.as_mut_ptr().write(SerialPort::new_with_store(
+            USB_BUS.as_ref().unwrap(),
+            [0; USB_PACKET_SIZE],
+            [0; USB_PACKET_SIZE],
+        ));
+        USB_SERIAL = Some(&mut *USB_SERIAL_STORAGE.as_mut_ptr());
+        let usb_dev = UsbDeviceBuilder::new(USB_BUS.as_ref().unwrap(), UsbVidPid(device_config.usb_vid, device_config.usb_pid))
             .manufacturer("Prototype")
             .product("UART MATRIX")
-            .serial_number("PROTOTYPE")
+            .serial_number(device_config.usb_serial.as_str())
             .device_class(USB_CLASS_CDC)
             .max_packet_size_0(64)
             .build();
 
-        USB_DEVICE = Some(usb_dev);
+        USB_DEVICE_STORAGE.as_mut_ptr().write(usb_dev);
+        USB_DEVICE = Some(&mut *USB_DEVICE_STORAGE.as_mut_ptr());
 
         //0x40010C0C is address of GPIOB output register
-        DISPLAY = Some(Hub75::new(&mut *(0x40010C0C as *mut u16)));
+        let port = RawPort::new(0x40010C0C as *mut u16);
+        //Configuration only holds geometries that fit the framebuffer
+        let display = Hub75::init(&mut DISPLAY_STORAGE, port, device_config.geometry).unwrap();
+        #[cfg(all(feature = "bcm", not(feature = "dma")))]
+        display.set_refresh_rate(device_config.refresh_rate as u32);
+        DISPLAY = Some(display);
 
         //Setting priorities and enabling interrupts
 
@@ -186,59 +248,112 @@ fn main() -> ! {
         NVIC::unmask(Interrupt::USART1);
         NVIC::unmask(Interrupt::USB_HP_CAN_TX);
         NVIC::unmask(Interrupt::USB_LP_CAN_RX0);
-        NVIC::unmask(Interrupt::TIM2);
         NVIC::unmask(Interrupt::TIM3);
 
-        DELAY = Some(Delay::new(p.SYST, clocks));
+        //Row switching has to be on time, otherwise rows would be lit unevenly
+        #[cfg(feature = "dma")]
+        {
+            p.NVIC.set_priority(Interrupt::DMA1_CHANNEL3, 0);
+            p.NVIC.set_priority(Interrupt::TIM1_UP, 0);
+            NVIC::unmask(Interrupt::DMA1_CHANNEL3);
+            NVIC::unmask(Interrupt::TIM1_UP);
+        }
+        #[cfg(not(feature = "dma"))]
+        NVIC::unmask(Interrupt::TIM2);
+
+        #[cfg(not(feature = "bcm"))]
+        {
+            DELAY = Some(Delay::new(p.SYST, clocks));
+        }
     }
 
     unsafe {
-        DISPLAY_MODE = DisplayMode::TextMode(TextDisplay::<256>::new());
-
-        if let DisplayMode::TextMode(tm) = &mut DISPLAY_MODE {
-            tm.write(0, String::from("TEST")).ok();
-            tm.write(1, String::from("TEST")).ok();
-            tm.write(2, String::from("TEST")).ok();
-
-            tm.set_animation(
-                1,
-                TextAnimation::BlinkingAnimation(BlinkingAnimation::new(64)),
-            )
-            .ok();
-            tm.set_animation(
-                2,
-                TextAnimation::SlideAnimation(SlideAnimation::new(4, SlideDirection::Left)),
-            )
-            .ok();
-
-            tm.set_color(0, (128, 128, 128)).ok();
-            tm.set_color(1, (255, 0, 0)).ok();
-            tm.set_color(2, (240, 120, 0)).ok();
-            tm.set_font(1, Font::Ibm).ok();
-            tm.set_font(2, Font::ProFont).ok();
-        }
+        BRIGHTNESS_SCHEDULE = BrightnessSchedule::new(device_config.anim_rate as u32 * 60);
+        boot_screen::start(&mut DISPLAY_MODE, DISPLAY.as_deref_mut().unwrap());
     }
 
-    let mut anim_timer = Timer::tim3(dp.TIM3, &clocks, &mut rcc.apb1).start_count_down(60.hz());
-    let mut draw_timer = Timer::tim2(dp.TIM2, &clocks, &mut rcc.apb1).start_count_down(120.hz());
+    let mut anim_timer = Timer::tim3(dp.TIM3, &clocks, &mut rcc.apb1)
+        .start_count_down((device_config.anim_rate as u32).hz());
+    anim_timer.listen(Event::Update);
 
+    #[cfg(not(feature = "dma"))]
+    {
+        let mut draw_timer = Timer::tim2(dp.TIM2, &clocks, &mut rcc.apb1)
+            .start_count_down((device_config.refresh_rate as u32).hz());
+        draw_timer.listen(Event::Update);
 
-    anim_timer.listen(Event::Update);
-    draw_timer.listen(Event::Update);
+        //Counts us, so the times of output_step are the timer's reload values.
+        //The prescaler is loaded with the first update
+        #[cfg(feature = "bcm")]
+        unsafe {
+            (*TIM2::ptr())
+                .psc
+                .write(|w| w.psc().bits((clocks.pclk1_tim().0 / 1_000_000 - 1) as u16));
+        }
+
+        unsafe {
+            DRAW_TIMER = Some(draw_timer);
+        }
+    }
 
     unsafe {
-        DRAW_TIMER = Some(draw_timer);
         ANIM_TIMER = Some(anim_timer);
     }
     loop {
         unsafe {
+            //The display isn't scanned out while the loop changes it, TIM2 stays pending
+            //and runs once the pass is done
+            #[cfg(not(any(feature = "dma", feature = "bcm")))]
+            NVIC::mask(Interrupt::TIM2);
+
+            if boot_screen::info_expired() {
+                boot_screen::show(&mut DISPLAY_MODE, DISPLAY.as_deref_mut().unwrap());
+            }
+
+            //Commands run between renders, interrupts only receive, send and scan out the panel
+            if let Some(c) = UART_RX.command() {
+                let response = parse_command(c, Link::Uart);
+                uart_send(c.0[0], response.as_bytes());
+                UART_RX.release();
+            }
+
+            if let Some(c) = USB_RX.command() {
+                let response = parse_command(c, Link::Usb);
+                usb_send(c.0[0], response.as_bytes());
+                USB_RX.release();
+            }
+
+            for _ in 0..ANIM_TICKS.swap(0, Ordering::Relaxed) {
+                if let DisplayMode::TextMode(tm) = &mut DISPLAY_MODE {
+                    tm.anim_tick()
+                };
+                if let Some(brightness) = BRIGHTNESS_SCHEDULE.tick() {
+                    Panel::set_brightness(DISPLAY.as_deref_mut().unwrap(), brightness);
+                }
+            }
+
             if let DisplayMode::TextMode(tm) = &mut DISPLAY_MODE {
-                tm.update(DISPLAY.as_mut().unwrap())
+                tm.update(DISPLAY.as_deref_mut().unwrap())
             };
-            if CLEAR_FLAG.load(Ordering::Relaxed) == true{
-                DISPLAY.as_mut().unwrap().clear_display();
+            if CLEAR_FLAG.load(Ordering::Relaxed) {
+                DISPLAY.as_deref_mut().unwrap().clear_display();
                 CLEAR_FLAG.store(false, Ordering::Relaxed);
             }
+
+            #[cfg(feature = "dma")]
+            {
+                let display = DISPLAY.as_deref_mut().unwrap();
+                if OUTPUT_ENABLED != display.dma_running() {
+                    if OUTPUT_ENABLED {
+                        display.start_dma();
+                    } else {
+                        display.stop_dma();
+                    }
+                }
+            }
+
+            #[cfg(not(any(feature = "dma", feature = "bcm")))]
+            NVIC::unmask(Interrupt::TIM2);
         }
     }
 }
@@ -249,37 +364,32 @@ unsafe fn USART1() {
 
     let result = rx.read();
     if let Ok(byte) = result {
-        UARTCONTROLLER.as_mut().unwrap().read_byte(byte);
-    }
-
-    let command = UARTCONTROLLER.as_mut().unwrap().get_command();
+        UART_RX.read_byte(byte);
 
-    if let Some(c) = command {
-        let response = parse_command(&c);
-        let crc = crc::crc8_ccitt_response(c[0], response);
+        //Refused if the main loop is still busy with the previous frame
+        if let Some(command) = UART_RX.queue_command() {
+            uart_send(command, DisplayError::Overflow.message().as_bytes());
+        }
+    }
 
-        uart_transmit_block("UMX".as_bytes());
-        let len = (response.len() + 1) as u16;
+    rx.listen();
 
-        //send length
-        uart_transmit_block(&[(len >> 8) as u8]);
-        uart_transmit_block(&[len as u8]);
+    //Writes the next byte whenever the transmit register is empty
+    let tx = SERIAL_TX.as_mut().unwrap();
+    UART_TX.flush(|data| tx.write(data[0]).map(|_| 1)).ok();
 
-        //send command code
-        uart_transmit_block(&[c[0]]);
-        uart_transmit_block(response);
-        uart_transmit_block(&[crc]);
+    if UART_TX.is_empty() {
+        tx.unlisten();
     }
-
-    rx.listen();
 }
 
+#[cfg(not(any(feature = "dma", feature = "bcm")))]
 #[interrupt]
 unsafe fn TIM2() {
 
     if OUTPUT_ENABLED {
         DISPLAY
-            .as_mut()
+            .as_deref_mut()
             .unwrap()
             .output(DELAY.as_mut().unwrap());
     }
@@ -287,11 +397,44 @@ unsafe fn TIM2() {
     DRAW_TIMER.as_mut().unwrap().clear_update_interrupt_flag();
 }
 
+//Every call shows the next bitplane and reloads the timer for as long as it stays lit
+#[cfg(all(feature = "bcm", not(feature = "dma")))]
 #[interrupt]
-unsafe fn TIM3() {
-    if let DisplayMode::TextMode(tm) = &mut DISPLAY_MODE {
-        tm.anim_tick()
+unsafe fn TIM2() {
+    let display = DISPLAY.as_deref_mut().unwrap();
+    DRAW_TIMER.as_mut().unwrap().clear_update_interrupt_flag();
+
+    let wait_us = if OUTPUT_ENABLED {
+        display.output_step()
+    } else {
+        display.blank();
+        0
     };
+
+    //Nothing to show, checks again later. The timer counts us, see main
+    let ticks = if wait_us == 0 { BCM_IDLE_US } else { wait_us };
+    let timer = &*TIM2::ptr();
+    timer.cnt.reset();
+    timer.arr.write(|w| w.arr().bits((ticks.clamp(2, 0x1_0000) - 1) as u16));
+}
+
+#[cfg(feature = "dma")]
+#[interrupt]
+unsafe fn DMA1_CHANNEL3() {
+    DISPLAY.as_deref_mut().unwrap().on_shift_complete();
+}
+
+#[cfg(feature = "dma")]
+#[interrupt]
+unsafe fn TIM1_UP() {
+    DISPLAY.as_deref_mut().unwrap().on_lit_complete();
+}
+
+#[interrupt]
+unsafe fn TIM3() {
+    boot_screen::tick();
+    link::tick();
+    ANIM_TICKS.fetch_add(1, Ordering::Relaxed);
     ANIM_TIMER.as_mut().unwrap().clear_update_interrupt_flag();
 }
 
@@ -306,68 +449,110 @@ fn USB_LP_CAN_RX0() {
 }
 
 unsafe fn usb_interrupt() {
-    let usb_dev = USB_DEVICE.as_mut().unwrap();
-    let serial = USB_SERIAL.as_mut().unwrap();
+    let usb_dev = USB_DEVICE.as_deref_mut().unwrap();
+    let serial = USB_SERIAL.as_deref_mut().unwrap();
+
+    let polled = usb_dev.poll(&mut [serial]);
 
-    if !usb_dev.poll(&mut [serial]) {
-        return;
+    //Responses queued before the host reset the bus are never read
+    if usb_dev.state() == UsbDeviceState::Default {
+        USB_TX.clear();
     }
 
-    let mut buf = [0_u8; 1024];
+    if polled {
+        //One packet at a time, the rest stays in the endpoint until the next read
+        let mut buf = [0_u8; USB_PACKET_SIZE];
 
-    if let Ok(count) = serial.read(&mut buf) {
-        (0..count).for_each(|i| {
-            UARTCONTROLLER.as_mut().unwrap().read_byte(buf[i]);
-        });
+        while let Ok(count) = serial.read(&mut buf) {
+            if count == 0 {
+                break;
+            }
 
-        let command = UARTCONTROLLER.as_mut().unwrap().get_command();
+            for byte in &buf[..count] {
+                USB_RX.read_byte(*byte);
 
-        if let Some(c) = command {
-            let response = parse_command(&c);
-            serial.write(response).ok();
-            let crc = crc::crc8_ccitt_response(c[0], response);
+                //Refused if the main loop is still busy with the previous frame,
+                //written by usb_flush below as serial is still borrowed here
+                if let Some(command) = USB_RX.queue_command() {
+                    let message = DisplayError::Overflow.message().as_bytes();
+                    let crc = crc::crc8_ccitt_response(command, message);
+                    USB_TX.push_frame(command, message, crc).ok();
+                }
+            }
+        }
+    }
 
-            serial.write("UMX".as_bytes()).ok();
+    //Retried on every interrupt, including the one after the host read a packet
+    usb_flush();
+}
 
-            let len = (response.len() + 1) as u16;
+/// Queues the framed response and writes as much as the endpoint takes,
+/// the rest follows from the USB interrupt
+fn usb_send(command: u8, response: &[u8]) {
+    let crc = crc::crc8_ccitt_response(command, response);
 
-            //send length
-            serial.write(&[(len >> 8) as u8]).ok();
-            serial.write(&[len as u8]).ok();
-            //send command code
-            serial.write(&[c[0]]).ok();
-            serial.write(response).ok();
-            serial.write(&[crc]).ok();
-        }
-    }
+    cortex_m::interrupt::free(|_| unsafe {
+        //Response is dropped if the host doesn't read previous ones
+        USB_TX.push_frame(command, response, crc).ok();
+        usb_flush();
+    });
 }
 
-fn parse_command(buffer: &[u8]) -> &[u8] {
-    let command = interpret_command::<256, 64>(&buffer);
+unsafe fn usb_flush() {
+    let serial = USB_SERIAL.as_deref_mut().unwrap();
+
+    //Other errors leave the queue as is, writing is retried on the next interrupt
+    USB_TX
+        .flush(|data| match serial.write(data) {
+            Ok(count) => Ok(count),
+            Err(UsbError::WouldBlock) => Err(nb::Error::WouldBlock),
+            Err(e) => Err(nb::Error::Other(e)),
+        })
+        .ok();
+}
+
+fn parse_command(frame: &Frame<RX_BUFFER_SIZE>, link: Link) -> Response {
+    //Refused before interpreting, so the busy link doesn't change anything
+    if !link::accept(link) {
+        return Response::Static(DisplayError::LinkBusy.message());
+    }
+
+    //DrawRow carries up to two chained 64 pixel wide panels, wider rows are sent in segments
+    let command = interpret_command::<256, 128>(&frame.0, frame.1);
     match command {
         Ok(command) => unsafe {
+            //Commands apply to the boot screen, not to device info
+            if boot_screen::cancel_info() {
+                boot_screen::show(&mut DISPLAY_MODE, DISPLAY.as_deref_mut().unwrap());
+            }
+
             let result = command.execute(
                 &mut DISPLAY_MODE,
-                DISPLAY.as_mut().unwrap(),
+                DISPLAY.as_deref_mut().unwrap(),
                 &mut OUTPUT_ENABLED,
                 &mut CLEAR_FLAG,
+                &mut BRIGHTNESS_SCHEDULE,
             );
 
             match result {
-                Ok(response) => response.as_bytes(),
-                Err(e) => e.message().as_bytes(),
+                Ok(response) => response,
+                Err(e) => Response::Static(e.message()),
             }
         },
         Err(e) => {
-            return e.message().as_bytes();
+            return Response::Static(e.message());
         }
     }
 }
 
-fn uart_transmit_block(message: &[u8]) {
-    let tx = unsafe { SERIAL_TX.as_mut().unwrap() };
-    for character in message {
-        block!(tx.write(*character)).ok();
-    }
-    tx.flush().ok();
+/// Queues the framed response and starts the transmit interrupt
+fn uart_send(command: u8, response: &[u8]) {
+    let crc = crc::crc8_ccitt_response(command, response);
+
+    cortex_m::interrupt::free(|_| unsafe {
+        //Response is dropped if the previous ones are still being sent
+        if UART_TX.push_frame(command, response, crc).is_ok() {
+            SERIAL_TX.as_mut().unwrap().listen();
+        }
+    });
 }
@@ -0,0 +1,77 @@
+use umx_core::saved_state::{
+    framebuffer_len, restore_framebuffer, save_framebuffer, DIRECT_MODE, TEXT_MODE,
+};
+
+use crate::{
+    display::{panel::Panel, DisplayError, DisplayMode},
+    storage::{Record, Sink},
+};
+
+//Last 4 KB of flash, reserved in memory.x. Version has to be increased
+//whenever the layout of saved data changes, see umx_core::saved_state
+const STATE: Record = Record::new(0xF000, 4, 1);
+
+/// Stores the current mode with its content, restored by restore at boot.
+/// Fails with OutOfBounds before erasing the previous state if a framebuffer with
+/// more bitplanes than fit the record is shown
+pub fn save<T: Panel, const TEXT_ROW_LENGTH: usize>(
+    mode: &DisplayMode<TEXT_ROW_LENGTH>,
+    target: &T,
+) -> Result<(), DisplayError> {
+    if let DisplayMode::DirectMode = mode {
+        if 1 + framebuffer_len(target.color_words()) > STATE.capacity() {
+            return Err(DisplayError::OutOfBounds);
+        }
+    }
+
+    STATE.save(|writer| match mode {
+        DisplayMode::TextMode(text_display) => {
+            writer.push(&[TEXT_MODE])?;
+            text_display.save(writer)
+        }
+        DisplayMode::DirectMode => {
+            writer.push(&[DIRECT_MODE])?;
+            save_framebuffer(writer, target.color_words(), |index| {
+                target.color_bits(index)
+            })
+        }
+    })
+}
+
+/// Removes saved state, the display boots with defaults again
+pub fn erase() -> Result<(), DisplayError> {
+    STATE.erase()
+}
+
+/// Returns false if there is no valid saved state, mode and target are left untouched
+/// if nothing was saved and may be partly restored if saved data is incomplete
+pub fn restore<T: Panel, const TEXT_ROW_LENGTH: usize>(
+    mode: &mut DisplayMode<TEXT_ROW_LENGTH>,
+    target: &mut T,
+) -> bool {
+    let mut reader = match STATE.load() {
+        Some(reader) => reader,
+        None => return false,
+    };
+
+    match reader.read_u8() {
+        Some(TEXT_MODE) => match mode.set_text_mode() {
+            Some(text_display) => text_display.restore(&mut reader).is_some(),
+            None => false,
+        },
+        Some(DIRECT_MODE) => {
+            //Fails if the framebuffer was saved with another geometry or color depth
+            let words = target.color_words();
+            let restored = restore_framebuffer(&mut reader, words, |index, bits| {
+                target.set_color_bits(index, bits)
+            });
+            if restored.is_none() {
+                return false;
+            }
+
+            *mode = DisplayMode::DirectMode;
+            true
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,150 @@
+use stm32f1xx_hal::flash::{self, FlashSize, SectorSize};
+
+use umx_core::record::{self, crc16_ccitt, HEADER_LEN};
+pub use umx_core::record::{RecordReader, Sink};
+
+use crate::display::DisplayError;
+
+const FLASH_START: u32 = 0x0800_0000;
+const PAGE_SIZE: usize = 1024;
+
+//Flash is programmed by half-words, so the buffer is flushed in even chunks
+const CHUNK_SIZE: usize = 64;
+
+static mut FLASH: Option<flash::Parts> = None;
+
+/// Hands over the flash peripheral, has to be called before saving any record
+pub fn init(flash: flash::Parts) {
+    unsafe {
+        FLASH = Some(flash);
+    }
+}
+
+/// Area of flash pages reserved in memory.x holding a single block of data, laid out
+/// as in umx_core::record
+pub struct Record {
+    //from the start of flash, aligned to a page
+    offset: u32,
+    pages: usize,
+    version: u16,
+}
+
+impl Record {
+    pub const fn new(offset: u32, pages: usize, version: u16) -> Self {
+        Record {
+            offset,
+            pages,
+            version,
+        }
+    }
+
+    /// Bytes of data the record can hold
+    pub fn capacity(&self) -> usize {
+        self.pages * PAGE_SIZE - HEADER_LEN
+    }
+
+    fn bytes(&self) -> &'static [u8] {
+        let address = (FLASH_START + self.offset) as *const u8;
+        unsafe { core::slice::from_raw_parts(address, self.pages * PAGE_SIZE) }
+    }
+
+    /// Returns saved data if there is a valid one
+    pub fn load(&self) -> Option<RecordReader<'static>> {
+        record::body(self.bytes(), self.version).map(RecordReader::new)
+    }
+
+    /// Erases the record and stores data pushed by save_data
+    pub fn save<F>(&self, save_data: F) -> Result<(), DisplayError>
+    where
+        F: FnOnce(&mut RecordWriter) -> Result<(), DisplayError>,
+    {
+        self.erase()?;
+
+        let mut writer = RecordWriter {
+            record: self,
+            buffer: [0; CHUNK_SIZE],
+            buffered: 0,
+            written: 0,
+            crc: 0xFFFF,
+        };
+
+        save_data(&mut writer)?;
+        writer.finish()
+    }
+
+    /// Removes saved data
+    pub fn erase(&self) -> Result<(), DisplayError> {
+        with_writer(|writer| writer.erase(self.offset, self.pages * PAGE_SIZE))
+    }
+}
+
+fn with_writer<F>(f: F) -> Result<(), DisplayError>
+where
+    F: FnOnce(&mut flash::FlashWriter) -> Result<(), flash::Error>,
+{
+    let flash = unsafe { FLASH.as_mut().ok_or(DisplayError::StorageError)? };
+    let mut writer = flash.writer(SectorSize::Sz1K, FlashSize::Sz64K);
+
+    f(&mut writer).map_err(|_| DisplayError::StorageError)
+}
+
+/// Streams data into an erased record, the header is written last,
+/// so the record stays invalid until everything is stored
+pub struct RecordWriter<'a> {
+    record: &'a Record,
+    buffer: [u8; CHUNK_SIZE],
+    buffered: usize,
+    //bytes of the body already in flash
+    written: usize,
+    crc: u16,
+}
+
+impl<'a> Sink for RecordWriter<'a> {
+    fn push(&mut self, data: &[u8]) -> Result<(), DisplayError> {
+        if self.written + self.buffered + data.len() > self.record.capacity() {
+            return Err(DisplayError::OutOfBounds);
+        }
+
+        self.crc = crc16_ccitt(self.crc, data);
+
+        for byte in data {
+            self.buffer[self.buffered] = *byte;
+            self.buffered += 1;
+
+            if self.buffered == CHUNK_SIZE {
+                self.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> RecordWriter<'a> {
+    fn flush(&mut self) -> Result<(), DisplayError> {
+        if self.buffered == 0 {
+            return Ok(());
+        }
+
+        //Odd length can only be left at the end, padding byte is not part of the body
+        let length = (self.buffered + 1) & !1;
+        let offset = self.record.offset + (HEADER_LEN + self.written) as u32;
+        let chunk = &self.buffer[..length];
+
+        with_writer(|writer| writer.write(offset, chunk))?;
+
+        self.written += self.buffered;
+        self.buffered = 0;
+
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(), DisplayError> {
+        self.flush()?;
+
+        let header = record::header(self.record.version, self.written, self.crc);
+
+        let offset = self.record.offset;
+        with_writer(|writer| writer.write(offset, &header))
+    }
+}
@@ -1,36 +1,58 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
 use crate::crc;
 
 const HEADER: [u8; 3] = [85, 77, 88];
 
+/// Longest command frame that can be received, including the header.
+/// Smaller with bcm, which takes the RAM for more bitplanes
+#[cfg(not(feature = "bcm"))]
+pub const RX_BUFFER_SIZE: usize = 512;
+#[cfg(feature = "bcm")]
+pub const RX_BUFFER_SIZE: usize = 288;
+
 pub enum UartState {
     AwaitingHeader,
     ReceivingCommand,
     CommandReceived,
 }
 
+/// Received command zero padded to the buffer size, and its length without CRC
+pub type Frame<const RX_BUFFER_SIZE: usize> = ([u8; RX_BUFFER_SIZE], usize);
+
+/// Receives frames in turns into two buffers, while the main loop executes one of them
+/// the next one is received into the other
 pub struct UartController<const RX_BUFFER_SIZE: usize> {
-    rx_buf: [u8; RX_BUFFER_SIZE],
+    frames: [Frame<RX_BUFFER_SIZE>; 2],
+    //frame bytes are written to, the other one is pending or free
+    receiving: usize,
     rx_offset: usize,
     bytes_to_read: usize,
     state: UartState,
+    //set by the interrupt when the other frame was received, cleared by release
+    pending: AtomicBool,
 }
 
 impl<const RX_BUFFER_SIZE: usize> UartController<RX_BUFFER_SIZE> {
     const HEADER_LEN: usize = 5;
 
-    pub fn new() -> Self {
+    pub const fn new() -> Self {
         UartController {
-            rx_buf: [0; RX_BUFFER_SIZE],
+            frames: [([0; RX_BUFFER_SIZE], 0), ([0; RX_BUFFER_SIZE], 0)],
+            receiving: 0,
             rx_offset: 0,
             bytes_to_read: 0,
             state: UartState::AwaitingHeader,
+            pending: AtomicBool::new(false),
         }
     }
 
     pub fn read_byte(&mut self, byte: u8) {
+        let rx_buf = &mut self.frames[self.receiving].0;
+
         match self.state {
             UartState::AwaitingHeader => {
-                self.rx_buf[self.rx_offset] = byte;
+                rx_buf[self.rx_offset] = byte;
 
                 if self.rx_offset < 3 && byte != HEADER[self.rx_offset] {
                     self.reset();
@@ -42,9 +64,9 @@ impl<const RX_BUFFER_SIZE: usize> UartController<RX_BUFFER_SIZE> {
                 //We got 5 bytes that should be the command header UMX
                 if self.rx_offset >= Self::HEADER_LEN {
                     //Check the magic numbers to make sure we're receiving valid packet
-                    if self.rx_buf[0..3] == [85, 77, 88] {
+                    if rx_buf[0..3] == [85, 77, 88] {
                         //Update bytes_to_read with packet length
-                        let bytes_to_read = ( (self.rx_buf[3] as u16) << 8) | self.rx_buf[4] as u16;
+                        let bytes_to_read = ( (rx_buf[3] as u16) << 8) | rx_buf[4] as u16;
                         self.reset();
                         //+ 1 because of CRC byte
                         self.bytes_to_read = bytes_to_read as usize + 1;
@@ -56,7 +78,7 @@ impl<const RX_BUFFER_SIZE: usize> UartController<RX_BUFFER_SIZE> {
             }
 
             UartState::ReceivingCommand => {
-                self.rx_buf[self.rx_offset] = byte;
+                rx_buf[self.rx_offset] = byte;
                 self.rx_offset += 1;
 
                 if self.rx_offset >= self.bytes_to_read {
@@ -69,27 +91,53 @@ impl<const RX_BUFFER_SIZE: usize> UartController<RX_BUFFER_SIZE> {
     }
 
     pub fn reset(&mut self) {
-        self.rx_buf.fill(0);
+        self.frames[self.receiving].0.fill(0);
         self.rx_offset = 0;
         self.bytes_to_read = 0;
         self.state = UartState::AwaitingHeader;
     }
 
-    pub fn get_command(&mut self) -> Option<[u8; RX_BUFFER_SIZE]> {
+    /// Hands a received frame with valid CRC over to the main loop, see `command`.
+    /// Returns the command code of a frame that was dropped because the main loop
+    /// didn't release the previous one yet
+    pub fn queue_command(&mut self) -> Option<u8> {
         if let UartState::CommandReceived = self.state {
-            let crc_check = crc::crc8_ccitt(&self.rx_buf[0..self.bytes_to_read]);
-            
-            if crc_check == 0 {
-                self.rx_buf[self.bytes_to_read-1] = 0;
-                let copy = self.rx_buf.clone();
+            let frame = &mut self.frames[self.receiving];
+            let crc_check = crc::crc8_ccitt(&frame.0[0..self.bytes_to_read]);
+            let command = frame.0[0];
+
+            if crc_check != 0 {
+                self.reset();
+                return None;
+            }
+
+            if self.pending.load(Ordering::Acquire) {
                 self.reset();
-                return Some(copy)
+                return Some(command);
             }
 
+            frame.0[self.bytes_to_read - 1] = 0;
+            frame.1 = self.bytes_to_read - 1;
+
+            self.receiving ^= 1;
+            self.pending.store(true, Ordering::Release);
             self.reset();
-            return None;
         }
 
-        return None;
+        None
+    }
+
+    /// Frame waiting to be executed, it stays unchanged until `release`
+    pub fn command(&self) -> Option<&Frame<RX_BUFFER_SIZE>> {
+        if self.pending.load(Ordering::Acquire) {
+            Some(&self.frames[self.receiving ^ 1])
+        } else {
+            None
+        }
+    }
+
+    /// Frees the buffer of the executed frame for the next one
+    pub fn release(&self) {
+        self.pending.store(false, Ordering::Release);
     }
 }
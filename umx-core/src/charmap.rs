@@ -0,0 +1,112 @@
+/// Unicode equivalents of CP437 characters 128-255
+pub const CP437_HIGH: &str = "ÇüéâäàåçêëèïîìÄÅÉæÆôöòûùÿÖÜ¢£¥₧ƒáíóúñÑªº¿⌐¬½¼¡«»░▒▓│┤╡╢╖╕╣║╗╝╜╛┐└┴┬├─┼╞╟╚╔╩╦╠═╬╧╨╤╥╙╘╒╓╫╪┘┌█▄▌▐▀αßΓπΣσµτΦΘΩδ∞φε∩≡±≥≤⌠⌡÷≈°∙·√ⁿ²■\u{a0}";
+
+//Characters that can be replaced with a similar one when the font lacks them
+const TRANSLITERATION: &[(char, &str)] = &[
+    ('A', "ÀÁÂÃÄÅĀĂĄ"),
+    ('a', "àáâãäåāăą"),
+    ('C', "ÇĆĈĊČ"),
+    ('c', "çćĉċč"),
+    ('D', "ĎĐ"),
+    ('d', "ďđ"),
+    ('E', "ÈÉÊËĒĔĖĘĚ"),
+    ('e', "èéêëēĕėęě"),
+    ('G', "ĜĞĠĢ"),
+    ('g', "ĝğġģ"),
+    ('H', "ĤĦ"),
+    ('h', "ĥħ"),
+    ('I', "ÌÍÎÏĨĪĬĮİ"),
+    ('i', "ìíîïĩīĭįı"),
+    ('J', "Ĵ"),
+    ('j', "ĵ"),
+    ('K', "Ķ"),
+    ('k', "ķĸ"),
+    ('L', "ĹĻĽĿŁ"),
+    ('l', "ĺļľŀł"),
+    ('N', "ÑŃŅŇ"),
+    ('n', "ñńņňŉ"),
+    ('O', "ÒÓÔÕÖØŌŎŐ"),
+    ('o', "òóôõöøōŏő"),
+    ('R', "ŔŖŘ"),
+    ('r', "ŕŗř"),
+    ('S', "ŚŜŞŠ"),
+    ('s', "ßśŝşš"),
+    ('T', "ŢŤ"),
+    ('t', "ţť"),
+    ('U', "ÙÚÛÜŨŪŬŮŰŲ"),
+    ('u', "ùúûüũūŭůűų"),
+    ('W', "Ŵ"),
+    ('w', "ŵ"),
+    ('Y', "ÝŶŸ"),
+    ('y', "ýÿŷ"),
+    ('Z', "ŹŻŽ"),
+    ('z', "źżž"),
+    ('\'', "‘’‚′"),
+    ('"', "“”„″«»"),
+    ('-', "‐‑–—−─━═"),
+    ('|', "│┃║"),
+    ('+', "┌┐└┘├┤┬┴┼╔╗╚╝╠╣╦╩╬"),
+    (' ', "\u{a0}"),
+];
+
+pub const REPLACEMENT_CHAR: char = '?';
+
+/// Returns the character that should be drawn in place of c with a font having glyphs
+/// for which has_glyph returns true, or None if the font has no reasonable substitute
+pub fn map_char<F: Fn(char) -> bool>(c: char, has_glyph: F) -> Option<char> {
+    if has_glyph(c) {
+        return Some(c);
+    }
+
+    TRANSLITERATION
+        .iter()
+        .find(|(_, variants)| contains_char(variants, c))
+        .map(|(base, _)| *base)
+        .filter(|base| has_glyph(*base))
+}
+
+/// Same as text.contains(c), str::contains brings in the generic pattern
+/// searcher which takes over 1 KB of flash
+pub fn contains_char(text: &str, c: char) -> bool {
+    text.chars().any(|other| other == c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ascii(c: char) -> bool {
+        (' '..='~').contains(&c)
+    }
+
+    fn cp437(c: char) -> bool {
+        ascii(c) || contains_char(CP437_HIGH, c)
+    }
+
+    #[test]
+    fn characters_of_the_font_are_kept() {
+        assert_eq!(map_char('a', ascii), Some('a'));
+        assert_eq!(map_char('é', cp437), Some('é'));
+        assert_eq!(map_char('░', cp437), Some('░'));
+    }
+
+    #[test]
+    fn missing_characters_are_transliterated() {
+        assert_eq!(map_char('é', ascii), Some('e'));
+        assert_eq!(map_char('Ł', ascii), Some('L'));
+        assert_eq!(map_char('ß', ascii), Some('s'));
+        assert_eq!(map_char('—', ascii), Some('-'));
+        assert_eq!(map_char('„', ascii), Some('"'));
+        assert_eq!(map_char('\u{a0}', ascii), Some(' '));
+        //CP437 has ╬ itself, other fonts draw it as +
+        assert_eq!(map_char('╬', ascii), Some('+'));
+    }
+
+    #[test]
+    fn characters_without_substitute_are_not_mapped() {
+        assert_eq!(map_char('€', ascii), None);
+        assert_eq!(map_char('░', ascii), None);
+        //Base character has to be in the font as well
+        assert_eq!(map_char('é', |c| c != 'e' && ascii(c)), None);
+    }
+}
@@ -0,0 +1,491 @@
+use core::fmt::Write as _;
+
+use heapless::{String, Vec};
+use hub75::{Geometry, Mapping};
+
+use crate::{
+    link::LinkPriority,
+    record::{RecordReader, Sink},
+    DisplayError,
+};
+
+//Enough for all bitplanes of a 64x32 panel, 8 KB for 4 bit color, 12 KB for 6 bit color with bcm
+#[cfg(not(feature = "bcm"))]
+pub const FRAMEBUFFER_WORDS: usize = 4096;
+#[cfg(feature = "bcm")]
+pub const FRAMEBUFFER_WORDS: usize = 6144;
+
+pub const USB_SERIAL_LENGTH: usize = 16;
+
+//Longest encoded value, the usb serial
+const MAX_VALUE_LENGTH: usize = USB_SERIAL_LENGTH;
+
+//Rows of the built-in screens
+pub const FONTS: usize = 3;
+
+/// Settings with values encoded as in SetConfig
+#[derive(Clone, Copy)]
+pub enum ConfigKey {
+    /// u32 bits per second
+    BaudRate,
+    /// u16
+    UsbVid,
+    /// u16
+    UsbPid,
+    /// Null terminated ASCII
+    UsbSerial,
+    /// Width and height as u16, scan rows, mapping and segment width as in SetGeometry
+    Geometry,
+    /// u16 Hz of the panel refresh timer, full scan-outs per second with bcm, not used with dma
+    RefreshRate,
+    /// u16 Hz of animation ticks
+    AnimRate,
+    /// Font ids of the rows of the built-in boot screen and device info
+    Fonts,
+    /// u8 link served when both send commands, 0 shared, 1 UART, 2 USB
+    LinkPriority,
+    /// u8 seconds device info is shown before the boot screen, 0 skips it
+    BootInfo,
+}
+
+impl ConfigKey {
+    const ALL: [ConfigKey; 10] = [
+        ConfigKey::BaudRate,
+        ConfigKey::UsbVid,
+        ConfigKey::UsbPid,
+        ConfigKey::UsbSerial,
+        ConfigKey::Geometry,
+        ConfigKey::RefreshRate,
+        ConfigKey::AnimRate,
+        ConfigKey::Fonts,
+        ConfigKey::LinkPriority,
+        ConfigKey::BootInfo,
+    ];
+
+    pub fn from_id(id: u8) -> Option<ConfigKey> {
+        Self::ALL.get(id as usize).copied()
+    }
+
+    fn id(self) -> u8 {
+        self as u8
+    }
+}
+
+/// Font of a row of the built-in screens, stored by its id
+pub trait ScreenFont: Copy {
+    /// Fonts of the rows unless configured
+    const DEFAULTS: [Self; FONTS];
+
+    /// Returns None for unknown ids and fonts that don't survive a restart
+    fn from_stored_id(id: u8) -> Option<Self>;
+
+    fn stored_id(&self) -> u8;
+}
+
+pub struct Config<F: ScreenFont> {
+    pub baud_rate: u32,
+    pub usb_vid: u16,
+    pub usb_pid: u16,
+    pub usb_serial: String<USB_SERIAL_LENGTH>,
+    pub geometry: Geometry,
+    pub refresh_rate: u16,
+    pub anim_rate: u16,
+    pub fonts: [F; FONTS],
+    pub link_priority: LinkPriority,
+    pub boot_info: u8,
+}
+
+impl<F: ScreenFont> Config<F> {
+    pub const DEFAULT: Self = Config {
+        baud_rate: 115200,
+        usb_vid: 0x16c0,
+        usb_pid: 0x27dd,
+        usb_serial: String::new(),
+        geometry: Geometry::new(64, 32, 8, Mapping::Stripe(32)),
+        refresh_rate: 120,
+        anim_rate: 60,
+        fonts: F::DEFAULTS,
+        link_priority: LinkPriority::Shared,
+        boot_info: 0,
+    };
+
+    //Used when no serial was stored, only an empty String can be built in const
+    const DEFAULT_USB_SERIAL: &'static str = "PROTOTYPE";
+
+    /// Defaults overridden by the values read from a stored record,
+    /// invalid or unknown entries are skipped
+    pub fn load(reader: Option<RecordReader>) -> Self {
+        let mut config = Self::DEFAULT;
+        config.usb_serial.push_str(Self::DEFAULT_USB_SERIAL).ok();
+
+        let mut reader = match reader {
+            Some(reader) => reader,
+            None => return config,
+        };
+
+        //entries of key id, value length and value
+        while let (Some(id), Some(length)) = (reader.read_u8(), reader.read_u8()) {
+            let value = match reader.read(length as usize) {
+                Some(value) => value,
+                None => break,
+            };
+
+            if let Some(key) = ConfigKey::from_id(id) {
+                config.set(key, value).ok();
+            }
+        }
+
+        config
+    }
+
+    /// Stores every value, read back by load
+    pub fn save<S: Sink>(&self, sink: &mut S) -> Result<(), DisplayError> {
+        for key in ConfigKey::ALL.iter() {
+            let value = self.encode(*key);
+
+            sink.push(&[key.id(), value.len() as u8])?;
+            sink.push(&value)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn set(&mut self, key: ConfigKey, value: &[u8]) -> Result<(), DisplayError> {
+        match key {
+            ConfigKey::BaudRate => {
+                let baud_rate = u32::from_be_bytes(read_array(value)?);
+                //USART1 runs from 72 MHz
+                if !(1200..=4_500_000).contains(&baud_rate) {
+                    return Err(DisplayError::InvalidSetting);
+                }
+
+                self.baud_rate = baud_rate;
+            }
+            ConfigKey::UsbVid => self.usb_vid = u16::from_be_bytes(read_array(value)?),
+            ConfigKey::UsbPid => self.usb_pid = u16::from_be_bytes(read_array(value)?),
+            ConfigKey::UsbSerial => {
+                let end = value.iter().position(|e| *e == 0).unwrap_or(value.len());
+                let serial = core::str::from_utf8(&value[..end])
+                    .map_err(|_| DisplayError::InvalidSetting)?;
+
+                if serial.is_empty() || !serial.is_ascii() {
+                    return Err(DisplayError::InvalidSetting);
+                }
+
+                let mut usb_serial = String::new();
+                usb_serial
+                    .push_str(serial)
+                    .map_err(|_| DisplayError::OutOfBounds)?;
+                self.usb_serial = usb_serial;
+            }
+            ConfigKey::Geometry => {
+                let geometry = decode_geometry(value)?;
+                //A single panel of the geometry needs at least one bitplane in the framebuffer
+                if !geometry.is_valid() || geometry.words() > FRAMEBUFFER_WORDS {
+                    return Err(DisplayError::InvalidSetting);
+                }
+
+                self.geometry = geometry;
+            }
+            ConfigKey::RefreshRate => self.refresh_rate = read_rate(value)?,
+            ConfigKey::AnimRate => self.anim_rate = read_rate(value)?,
+            ConfigKey::Fonts => {
+                let ids: [u8; FONTS] = read_array(value)?;
+
+                let mut fonts = F::DEFAULTS;
+                for (font, id) in fonts.iter_mut().zip(ids.iter()) {
+                    *font = F::from_stored_id(*id).ok_or(DisplayError::InvalidSetting)?;
+                }
+
+                self.fonts = fonts;
+            }
+            ConfigKey::LinkPriority => {
+                let [id]: [u8; 1] = read_array(value)?;
+                self.link_priority =
+                    LinkPriority::from_id(id).ok_or(DisplayError::InvalidSetting)?;
+            }
+            ConfigKey::BootInfo => {
+                let [seconds]: [u8; 1] = read_array(value)?;
+                self.boot_info = seconds;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Value in the format of set
+    fn encode(&self, key: ConfigKey) -> Vec<u8, MAX_VALUE_LENGTH> {
+        let mut value = Vec::new();
+
+        match key {
+            ConfigKey::BaudRate => value.extend_from_slice(&self.baud_rate.to_be_bytes()),
+            ConfigKey::UsbVid => value.extend_from_slice(&self.usb_vid.to_be_bytes()),
+            ConfigKey::UsbPid => value.extend_from_slice(&self.usb_pid.to_be_bytes()),
+            ConfigKey::UsbSerial => value.extend_from_slice(self.usb_serial.as_bytes()),
+            ConfigKey::Geometry => value.extend_from_slice(&encode_geometry(&self.geometry)),
+            ConfigKey::RefreshRate => value.extend_from_slice(&self.refresh_rate.to_be_bytes()),
+            ConfigKey::AnimRate => value.extend_from_slice(&self.anim_rate.to_be_bytes()),
+            ConfigKey::Fonts => {
+                for font in self.fonts.iter() {
+                    value.push(font.stored_id()).ok();
+                }
+                Ok(())
+            }
+            ConfigKey::LinkPriority => value.extend_from_slice(&[self.link_priority.id()]),
+            ConfigKey::BootInfo => value.extend_from_slice(&[self.boot_info]),
+        }
+        .ok();
+
+        value
+    }
+
+    /// Writes the value as text, e.g. "BaudRate:115200"
+    pub fn describe<const LENGTH: usize>(&self, key: ConfigKey, text: &mut String<LENGTH>) {
+        match key {
+            ConfigKey::BaudRate => write!(text, "BaudRate:{}", self.baud_rate),
+            ConfigKey::UsbVid => write!(text, "UsbVid:{:04X}", self.usb_vid),
+            ConfigKey::UsbPid => write!(text, "UsbPid:{:04X}", self.usb_pid),
+            ConfigKey::UsbSerial => write!(text, "UsbSerial:{}", self.usb_serial),
+            ConfigKey::Geometry => write!(
+                text,
+                "Width:{};Height:{};Scan:{};Mapping:",
+                self.geometry.width, self.geometry.height, self.geometry.scan_rows
+            )
+            .and_then(|_| match self.geometry.mapping {
+                Mapping::Direct => text.write_str("Direct"),
+                Mapping::Stripe(width) => write!(text, "Stripe({})", width),
+                Mapping::Zigzag(width) => write!(text, "Zigzag({})", width),
+            }),
+            ConfigKey::RefreshRate => write!(text, "RefreshRate:{}", self.refresh_rate),
+            ConfigKey::AnimRate => write!(text, "AnimRate:{}", self.anim_rate),
+            ConfigKey::Fonts => write!(
+                text,
+                "Fonts:{},{},{}",
+                self.fonts[0].stored_id(),
+                self.fonts[1].stored_id(),
+                self.fonts[2].stored_id()
+            ),
+            ConfigKey::LinkPriority => write!(text, "LinkPriority:{}", self.link_priority.name()),
+            ConfigKey::BootInfo => write!(text, "BootInfo:{}", self.boot_info),
+        }
+        .ok();
+    }
+}
+
+/// Width and height as u16, scan rows, mapping (0 direct, 1 stripe, 2 zigzag)
+/// and segment width of stripe and zigzag
+pub fn decode_geometry(bytes: &[u8]) -> Result<Geometry, DisplayError> {
+    let bytes: [u8; 7] = read_array(bytes)?;
+
+    let width = u16::from_be_bytes([bytes[0], bytes[1]]);
+    let height = u16::from_be_bytes([bytes[2], bytes[3]]);
+    let scan_rows = bytes[4] as u16;
+    let mapping = match bytes[5] {
+        0 => Mapping::Direct,
+        1 => Mapping::Stripe(bytes[6]),
+        2 => Mapping::Zigzag(bytes[6]),
+        _ => return Err(DisplayError::InvalidSetting),
+    };
+
+    Ok(Geometry::new(width, height, scan_rows, mapping))
+}
+
+fn encode_geometry(geometry: &Geometry) -> [u8; 7] {
+    let width = geometry.width.to_be_bytes();
+    let height = geometry.height.to_be_bytes();
+    let (mapping, segment) = match geometry.mapping {
+        Mapping::Direct => (0, 0),
+        Mapping::Stripe(segment) => (1, segment),
+        Mapping::Zigzag(segment) => (2, segment),
+    };
+
+    [
+        width[0],
+        width[1],
+        height[0],
+        height[1],
+        geometry.scan_rows as u8,
+        mapping,
+        segment,
+    ]
+}
+
+fn read_array<const N: usize>(value: &[u8]) -> Result<[u8; N], DisplayError> {
+    let mut array = [0; N];
+    array.copy_from_slice(value.get(..N).ok_or(DisplayError::OutOfBounds)?);
+
+    Ok(array)
+}
+
+fn read_rate(value: &[u8]) -> Result<u16, DisplayError> {
+    let rate = u16::from_be_bytes(read_array(value)?);
+    if rate == 0 {
+        return Err(DisplayError::InvalidSetting);
+    }
+
+    Ok(rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::tests::Body;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct TestFont(u8);
+
+    impl ScreenFont for TestFont {
+        const DEFAULTS: [Self; FONTS] = [TestFont(0), TestFont(2), TestFont(1)];
+
+        fn from_stored_id(id: u8) -> Option<Self> {
+            if id < 3 {
+                Some(TestFont(id))
+            } else {
+                None
+            }
+        }
+
+        fn stored_id(&self) -> u8 {
+            self.0
+        }
+    }
+
+    type TestConfig = Config<TestFont>;
+
+    fn load(entries: &[u8]) -> TestConfig {
+        Config::load(Some(RecordReader::new(entries)))
+    }
+
+    #[test]
+    fn defaults_apply_when_nothing_was_stored() {
+        let config = TestConfig::load(None);
+
+        assert_eq!(config.baud_rate, 115200);
+        assert_eq!(config.usb_serial, "PROTOTYPE");
+        assert_eq!(
+            config.geometry,
+            Geometry::new(64, 32, 8, Mapping::Stripe(32))
+        );
+        assert_eq!(config.fonts, TestFont::DEFAULTS);
+        assert_eq!(config.link_priority, LinkPriority::Shared);
+    }
+
+    #[test]
+    fn saved_values_are_loaded() {
+        let mut config = TestConfig::load(None);
+        config.baud_rate = 921_600;
+        config.usb_vid = 0x1234;
+        config.usb_pid = 0xABCD;
+        config.usb_serial = String::from("UMX-0001");
+        config.geometry = Geometry::new(32, 16, 8, Mapping::Zigzag(8));
+        config.refresh_rate = 240;
+        config.anim_rate = 30;
+        config.fonts = [TestFont(2), TestFont(2), TestFont(0)];
+        config.link_priority = LinkPriority::Usb;
+        config.boot_info = 5;
+
+        let mut body = Body::new(1018);
+        config.save(&mut body).unwrap();
+        let loaded = load(&body.data);
+
+        assert_eq!(loaded.baud_rate, 921_600);
+        assert_eq!(loaded.usb_vid, 0x1234);
+        assert_eq!(loaded.usb_pid, 0xABCD);
+        assert_eq!(loaded.usb_serial, "UMX-0001");
+        assert_eq!(loaded.geometry, config.geometry);
+        assert_eq!(loaded.refresh_rate, 240);
+        assert_eq!(loaded.anim_rate, 30);
+        assert_eq!(loaded.fonts, config.fonts);
+        assert_eq!(loaded.link_priority, LinkPriority::Usb);
+        assert_eq!(loaded.boot_info, 5);
+    }
+
+    #[test]
+    fn invalid_and_unknown_entries_are_skipped() {
+        #[rustfmt::skip]
+        let config = load(&[
+            //baud rate 0
+            0, 4, 0, 0, 0, 0,
+            //key of a newer firmware
+            99, 1, 7,
+            //anim rate 30
+            6, 2, 0, 30,
+        ]);
+
+        assert_eq!(config.baud_rate, 115200);
+        assert_eq!(config.anim_rate, 30);
+    }
+
+    #[test]
+    fn loading_stops_at_a_truncated_entry() {
+        let config = load(&[6, 2, 0, 30, 5, 2, 0]);
+
+        assert_eq!(config.anim_rate, 30);
+        assert_eq!(config.refresh_rate, 120);
+    }
+
+    #[test]
+    fn geometry_has_to_fit_the_framebuffer() {
+        let mut config = TestConfig::load(None);
+
+        //128x64, 1/32 scan takes 4096 words for every bitplane
+        assert_eq!(
+            config.set(ConfigKey::Geometry, &[0, 128, 0, 64, 32, 0, 0]),
+            Ok(())
+        );
+        assert_eq!(config.geometry.words(), 4096);
+
+        //256x64 takes twice as much
+        assert_eq!(
+            config.set(ConfigKey::Geometry, &[1, 0, 0, 64, 32, 0, 0]),
+            Err(DisplayError::InvalidSetting)
+        );
+        //1/3 scan
+        assert_eq!(
+            config.set(ConfigKey::Geometry, &[0, 64, 0, 32, 3, 0, 0]),
+            Err(DisplayError::InvalidSetting)
+        );
+        assert_eq!(config.geometry, Geometry::new(128, 64, 32, Mapping::Direct));
+    }
+
+    #[test]
+    fn fonts_lost_on_restart_are_refused() {
+        let mut config = TestConfig::load(None);
+
+        assert_eq!(
+            config.set(ConfigKey::Fonts, &[0, 1, 5]),
+            Err(DisplayError::InvalidSetting)
+        );
+        assert_eq!(config.fonts, TestFont::DEFAULTS);
+    }
+
+    #[test]
+    fn usb_serial_is_null_terminated_ascii() {
+        let mut config = TestConfig::load(None);
+
+        assert_eq!(config.set(ConfigKey::UsbSerial, b"UMX-7\0junk"), Ok(()));
+        assert_eq!(config.usb_serial, "UMX-7");
+        assert_eq!(
+            config.set(ConfigKey::UsbSerial, b"\0"),
+            Err(DisplayError::InvalidSetting)
+        );
+        assert_eq!(
+            config.set(ConfigKey::UsbSerial, "Zażółć".as_bytes()),
+            Err(DisplayError::InvalidSetting)
+        );
+        assert_eq!(
+            config.set(ConfigKey::UsbSerial, b"0123456789ABCDEFG"),
+            Err(DisplayError::OutOfBounds)
+        );
+        assert_eq!(config.usb_serial, "UMX-7");
+    }
+
+    #[test]
+    fn values_are_described_as_text() {
+        let config = TestConfig::load(None);
+        let mut text = String::<64>::new();
+
+        config.describe(ConfigKey::Geometry, &mut text);
+        assert_eq!(text, "Width:64;Height:32;Scan:8;Mapping:Stripe(32)");
+    }
+}
@@ -0,0 +1,41 @@
+//! Parts of the UART MATRIX firmware that don't touch flash or peripherals: encoding of
+//! stored settings and saved state, character mapping of text rows and link priority.
+//! The firmware wraps them with flash access and its statics.
+//!
+//! Tests run on the host: `cargo test -p umx-core --target x86_64-unknown-linux-gnu`
+
+#![no_std]
+
+pub mod charmap;
+pub mod config;
+pub mod link;
+pub mod proportional_font;
+pub mod record;
+pub mod saved_state;
+
+#[derive(Debug, PartialEq)]
+pub enum DisplayError {
+    OutOfBounds,
+    IncorrectMode,
+    InvalidSetting,
+    InvalidCommand,
+    DrawError,
+    StorageError,
+    LinkBusy,
+    Overflow,
+}
+
+impl DisplayError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            DisplayError::OutOfBounds => "Index out of Bounds",
+            DisplayError::IncorrectMode => "Incorrect Mode",
+            DisplayError::InvalidSetting => "Invalid Setting",
+            DisplayError::InvalidCommand => "Invalid Command",
+            DisplayError::DrawError => "Drawing Error",
+            DisplayError::StorageError => "Storage Error",
+            DisplayError::LinkBusy => "Link Busy",
+            DisplayError::Overflow => "Command Overflow",
+        }
+    }
+}
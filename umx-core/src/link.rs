@@ -0,0 +1,153 @@
+use core::sync::atomic::{AtomicU16, Ordering};
+
+/// Transport a command arrived on, its response is sent back the same way
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Link {
+    Uart,
+    Usb,
+}
+
+/// Which link is served when both send commands
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LinkPriority {
+    /// Commands of both links are executed in order of arrival
+    Shared,
+    /// USB commands are refused while UART is in use
+    Uart,
+    /// UART commands are refused while USB is in use
+    Usb,
+}
+
+impl LinkPriority {
+    pub fn from_id(id: u8) -> Option<LinkPriority> {
+        match id {
+            0 => Some(LinkPriority::Shared),
+            1 => Some(LinkPriority::Uart),
+            2 => Some(LinkPriority::Usb),
+            _ => None,
+        }
+    }
+
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LinkPriority::Shared => "Shared",
+            LinkPriority::Uart => "Uart",
+            LinkPriority::Usb => "Usb",
+        }
+    }
+
+    fn preferred(self) -> Option<Link> {
+        match self {
+            LinkPriority::Shared => None,
+            LinkPriority::Uart => Some(Link::Uart),
+            LinkPriority::Usb => Some(Link::Usb),
+        }
+    }
+}
+
+/// Keeps the other link out for a while after every command of the priority link
+pub struct LinkHold {
+    //Ticks left until the other link is accepted again
+    ticks: AtomicU16,
+}
+
+impl LinkHold {
+    pub const fn new() -> Self {
+        LinkHold {
+            ticks: AtomicU16::new(0),
+        }
+    }
+
+    /// Returns false if the command has to be refused because the other link has priority.
+    /// Every accepted command of the priority link holds it for hold_ticks
+    pub fn accept(&self, priority: LinkPriority, link: Link, hold_ticks: u16) -> bool {
+        match priority.preferred() {
+            None => true,
+            Some(preferred) if preferred == link => {
+                self.ticks.store(hold_ticks, Ordering::Relaxed);
+                true
+            }
+            Some(_) => self.ticks.load(Ordering::Relaxed) == 0,
+        }
+    }
+
+    /// Counts down the hold of the priority link
+    pub fn tick(&self) {
+        //A command preempting the tick renews the hold instead of being overwritten
+        self.ticks
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |ticks| {
+                ticks.checked_sub(1)
+            })
+            .ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_links_are_always_accepted() {
+        let hold = LinkHold::new();
+
+        assert!(hold.accept(LinkPriority::Shared, Link::Uart, 2));
+        assert!(hold.accept(LinkPriority::Shared, Link::Usb, 2));
+    }
+
+    #[test]
+    fn other_link_is_accepted_until_the_priority_link_is_used() {
+        let hold = LinkHold::new();
+
+        assert!(hold.accept(LinkPriority::Uart, Link::Usb, 2));
+        assert!(hold.accept(LinkPriority::Uart, Link::Uart, 2));
+        assert!(!hold.accept(LinkPriority::Uart, Link::Usb, 2));
+    }
+
+    #[test]
+    fn other_link_is_accepted_again_after_the_hold() {
+        let hold = LinkHold::new();
+        hold.accept(LinkPriority::Usb, Link::Usb, 2);
+
+        hold.tick();
+        assert!(!hold.accept(LinkPriority::Usb, Link::Uart, 2));
+        hold.tick();
+        assert!(hold.accept(LinkPriority::Usb, Link::Uart, 2));
+        //Stays at 0
+        hold.tick();
+        assert!(hold.accept(LinkPriority::Usb, Link::Uart, 2));
+    }
+
+    #[test]
+    fn every_command_of_the_priority_link_renews_the_hold() {
+        let hold = LinkHold::new();
+        hold.accept(LinkPriority::Uart, Link::Uart, 2);
+        hold.tick();
+
+        assert!(hold.accept(LinkPriority::Uart, Link::Uart, 2));
+        hold.tick();
+        assert!(!hold.accept(LinkPriority::Uart, Link::Usb, 2));
+    }
+
+    #[test]
+    fn priority_link_is_never_refused() {
+        let hold = LinkHold::new();
+        hold.accept(LinkPriority::Usb, Link::Usb, 100);
+
+        assert!(hold.accept(LinkPriority::Usb, Link::Usb, 100));
+        assert!(!hold.accept(LinkPriority::Usb, Link::Uart, 100));
+    }
+
+    #[test]
+    fn ids_match_names() {
+        for id in 0..3 {
+            let priority = LinkPriority::from_id(id).unwrap();
+            assert_eq!(priority.id(), id);
+            assert_eq!(priority.name(), ["Shared", "Uart", "Usb"][id as usize]);
+        }
+        assert_eq!(LinkPriority::from_id(3), None);
+    }
+}
@@ -0,0 +1,240 @@
+use embedded_graphics::{draw_target::DrawTarget, pixelcolor::BinaryColor, prelude::Point, Pixel};
+
+/// Bitmap of a single glyph, each row is stored MSB first and is at most 8 pixels wide
+#[derive(Debug, Clone, Copy)]
+struct Glyph {
+    width: u8,
+    rows: &'static [u8],
+}
+
+/// Bitmap font with per-glyph advance widths
+#[derive(Debug)]
+pub struct ProportionalFont {
+    //Every glyph takes height + 1 bytes, its width followed by its rows
+    pub glyphs: &'static [u8],
+    pub first_char: char,
+    pub height: u32,
+    //Offset from the top of the glyph to the baseline
+    pub baseline: u32,
+    //Empty columns between adjacent glyphs
+    pub spacing: u32,
+}
+
+impl ProportionalFont {
+    fn glyph(&self, c: char) -> Glyph {
+        let size = self.height as usize + 1;
+        let count = self.glyphs.len() / size;
+
+        //Fallback to '?' if the font has it, to first glyph otherwise
+        let mut index = (c as u32).wrapping_sub(self.first_char as u32) as usize;
+        if index >= count {
+            index = ('?' as u32).wrapping_sub(self.first_char as u32) as usize;
+        }
+        if index >= count {
+            index = 0;
+        }
+
+        let glyph = &self.glyphs[index * size..(index + 1) * size];
+        Glyph {
+            width: glyph[0],
+            rows: &glyph[1..],
+        }
+    }
+
+    /// Horizontal distance in pixels between the start of c and the start of the next glyph
+    pub fn advance(&self, c: char) -> u32 {
+        self.glyph(c).width as u32 + self.spacing
+    }
+
+    /// Draws pixels of c as BinaryColor::On with its left edge at position.x and baseline
+    /// at position.y, the rest of the glyph's cell is not drawn
+    pub fn draw_char<T: DrawTarget<Color = BinaryColor>>(
+        &self,
+        c: char,
+        position: Point,
+        target: &mut T,
+    ) -> Result<(), T::Error> {
+        let glyph = self.glyph(c);
+        let top = position.y - self.baseline as i32;
+        let width = glyph.width.min(8) as i32;
+
+        let pixels = glyph.rows.iter().enumerate().flat_map(|(y, row)| {
+            (0..width)
+                .filter(move |x| row & (0x80 >> x) != 0)
+                .map(move |x| Pixel(Point::new(position.x + x, top + y as i32), BinaryColor::On))
+        });
+
+        target.draw_iter(pixels)
+    }
+}
+
+//Proportional variant of the 6x9 font, glyphs trimmed to their ink width
+pub const NARROW_6X9: ProportionalFont = ProportionalFont {
+    #[rustfmt::skip]
+    glyphs: &[
+        2, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // ' '
+        1, 0x00, 0x80, 0x80, 0x80, 0x80, 0x00, 0x80, 0x00, 0x00, // '!'
+        3, 0x00, 0xA0, 0xA0, 0xA0, 0x00, 0x00, 0x00, 0x00, 0x00, // '"'
+        5, 0x00, 0x50, 0x50, 0xF8, 0x50, 0xF8, 0x50, 0x50, 0x00, // '#'
+        5, 0x20, 0x70, 0xA8, 0xA0, 0x70, 0x28, 0xA8, 0x70, 0x20, // '$'
+        6, 0x40, 0xA8, 0x48, 0x10, 0x20, 0x48, 0x54, 0x08, 0x00, // '%'
+        5, 0x00, 0x60, 0x90, 0x90, 0x60, 0x98, 0x90, 0x68, 0x00, // '&'
+        1, 0x00, 0x80, 0x80, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, // "'"
+        2, 0x00, 0x40, 0x80, 0x80, 0x80, 0x80, 0x80, 0x40, 0x00, // '('
+        2, 0x00, 0x80, 0x40, 0x40, 0x40, 0x40, 0x40, 0x80, 0x00, // ')'
+        5, 0x00, 0x00, 0x88, 0x50, 0xF8, 0x50, 0x88, 0x00, 0x00, // '*'
+        5, 0x00, 0x00, 0x20, 0x20, 0xF8, 0x20, 0x20, 0x00, 0x00, // '+'
+        2, 0x00, 0x00, 0x00, 0x00, 0x00, 0xC0, 0x40, 0x40, 0x80, // ','
+        5, 0x00, 0x00, 0x00, 0x00, 0xF8, 0x00, 0x00, 0x00, 0x00, // '-'
+        2, 0x00, 0x00, 0x00, 0x00, 0x00, 0xC0, 0xC0, 0x00, 0x00, // '.'
+        4, 0x00, 0x10, 0x10, 0x20, 0x40, 0x80, 0x80, 0x00, 0x00, // '/'
+        4, 0x00, 0x60, 0x90, 0x90, 0x90, 0x90, 0x60, 0x00, 0x00, // '0'
+        3, 0x00, 0x40, 0xC0, 0x40, 0x40, 0x40, 0xE0, 0x00, 0x00, // '1'
+        4, 0x00, 0x60, 0x90, 0x10, 0x20, 0x40, 0xF0, 0x00, 0x00, // '2'
+        4, 0x00, 0xF0, 0x20, 0x60, 0x10, 0x10, 0xE0, 0x00, 0x00, // '3'
+        5, 0x00, 0x10, 0x30, 0x50, 0x90, 0xF8, 0x10, 0x00, 0x00, // '4'
+        4, 0x00, 0xF0, 0x80, 0xE0, 0x10, 0x10, 0xE0, 0x00, 0x00, // '5'
+        4, 0x00, 0x60, 0x80, 0xE0, 0x90, 0x90, 0x60, 0x00, 0x00, // '6'
+        4, 0x00, 0xF0, 0x10, 0x10, 0x20, 0x40, 0x40, 0x00, 0x00, // '7'
+        4, 0x00, 0x60, 0x90, 0x60, 0x90, 0x90, 0x60, 0x00, 0x00, // '8'
+        4, 0x00, 0x60, 0x90, 0x90, 0x70, 0x10, 0x60, 0x00, 0x00, // '9'
+        2, 0x00, 0x00, 0xC0, 0xC0, 0x00, 0xC0, 0xC0, 0x00, 0x00, // ':'
+        2, 0x00, 0x00, 0xC0, 0xC0, 0x00, 0xC0, 0x40, 0x40, 0x80, // ';'
+        5, 0x00, 0x00, 0x18, 0x60, 0x80, 0x60, 0x18, 0x00, 0x00, // '<'
+        5, 0x00, 0x00, 0x00, 0xF8, 0x00, 0xF8, 0x00, 0x00, 0x00, // '='
+        5, 0x00, 0x00, 0xC0, 0x30, 0x08, 0x30, 0xC0, 0x00, 0x00, // '>'
+        4, 0x60, 0x90, 0x10, 0x60, 0x40, 0x00, 0x40, 0x00, 0x00, // '?'
+        5, 0x00, 0x70, 0x90, 0xA8, 0xB0, 0x80, 0x70, 0x00, 0x00, // '@'
+        5, 0x00, 0x20, 0x50, 0x88, 0xF8, 0x88, 0x88, 0x00, 0x00, // 'A'
+        5, 0x00, 0xF0, 0x88, 0xF0, 0x88, 0x88, 0xF0, 0x00, 0x00, // 'B'
+        4, 0x00, 0x60, 0x90, 0x80, 0x80, 0x90, 0x60, 0x00, 0x00, // 'C'
+        4, 0x00, 0xE0, 0x90, 0x90, 0x90, 0x90, 0xE0, 0x00, 0x00, // 'D'
+        4, 0x00, 0xF0, 0x80, 0xE0, 0x80, 0x80, 0xF0, 0x00, 0x00, // 'E'
+        4, 0x00, 0xF0, 0x80, 0xE0, 0x80, 0x80, 0x80, 0x00, 0x00, // 'F'
+        4, 0x00, 0x60, 0x90, 0x80, 0xB0, 0x90, 0x60, 0x00, 0x00, // 'G'
+        4, 0x00, 0x90, 0x90, 0xF0, 0x90, 0x90, 0x90, 0x00, 0x00, // 'H'
+        3, 0x00, 0xE0, 0x40, 0x40, 0x40, 0x40, 0xE0, 0x00, 0x00, // 'I'
+        5, 0x00, 0x38, 0x10, 0x10, 0x10, 0x90, 0x60, 0x00, 0x00, // 'J'
+        4, 0x00, 0x90, 0xA0, 0xC0, 0xA0, 0x90, 0x90, 0x00, 0x00, // 'K'
+        4, 0x00, 0x80, 0x80, 0x80, 0x80, 0x80, 0xF0, 0x00, 0x00, // 'L'
+        5, 0x00, 0x88, 0xD8, 0xA8, 0xA8, 0x88, 0x88, 0x00, 0x00, // 'M'
+        4, 0x00, 0x90, 0xD0, 0xB0, 0x90, 0x90, 0x90, 0x00, 0x00, // 'N'
+        5, 0x00, 0x70, 0x88, 0x88, 0x88, 0x88, 0x70, 0x00, 0x00, // 'O'
+        4, 0x00, 0xE0, 0x90, 0x90, 0xE0, 0x80, 0x80, 0x00, 0x00, // 'P'
+        4, 0x00, 0x60, 0x90, 0x90, 0xD0, 0xB0, 0x60, 0x10, 0x00, // 'Q'
+        4, 0x00, 0xE0, 0x90, 0x90, 0xE0, 0x90, 0x90, 0x00, 0x00, // 'R'
+        4, 0x00, 0x60, 0x90, 0x40, 0x20, 0x90, 0x60, 0x00, 0x00, // 'S'
+        5, 0x00, 0xF8, 0x20, 0x20, 0x20, 0x20, 0x20, 0x00, 0x00, // 'T'
+        4, 0x00, 0x90, 0x90, 0x90, 0x90, 0x90, 0x60, 0x00, 0x00, // 'U'
+        4, 0x00, 0x90, 0x90, 0x90, 0xF0, 0x60, 0x60, 0x00, 0x00, // 'V'
+        5, 0x00, 0x88, 0x88, 0xA8, 0xA8, 0xD8, 0x88, 0x00, 0x00, // 'W'
+        5, 0x00, 0x88, 0x50, 0x20, 0x20, 0x50, 0x88, 0x00, 0x00, // 'X'
+        5, 0x00, 0x88, 0x88, 0x50, 0x20, 0x20, 0x20, 0x00, 0x00, // 'Y'
+        4, 0x00, 0xF0, 0x10, 0x20, 0x40, 0x80, 0xF0, 0x00, 0x00, // 'Z'
+        3, 0x00, 0xE0, 0x80, 0x80, 0x80, 0x80, 0xE0, 0x00, 0x00, // '['
+        4, 0x00, 0x80, 0x80, 0x40, 0x20, 0x10, 0x10, 0x00, 0x00, // '\'
+        3, 0x00, 0xE0, 0x20, 0x20, 0x20, 0x20, 0xE0, 0x00, 0x00, // ']'
+        5, 0x00, 0x20, 0x50, 0x88, 0x00, 0x00, 0x00, 0x00, 0x00, // '^'
+        5, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xF8, // '_'
+        2, 0x00, 0x80, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // '`'
+        4, 0x00, 0x00, 0x00, 0x70, 0x90, 0x90, 0x70, 0x00, 0x00, // 'a'
+        4, 0x00, 0x80, 0x80, 0xE0, 0x90, 0x90, 0xE0, 0x00, 0x00, // 'b'
+        4, 0x00, 0x00, 0x00, 0x70, 0x80, 0x80, 0x70, 0x00, 0x00, // 'c'
+        4, 0x00, 0x10, 0x10, 0x70, 0x90, 0x90, 0x70, 0x00, 0x00, // 'd'
+        4, 0x00, 0x00, 0x00, 0x60, 0xB0, 0xC0, 0x70, 0x00, 0x00, // 'e'
+        4, 0x00, 0x20, 0x50, 0x40, 0xE0, 0x40, 0x40, 0x00, 0x00, // 'f'
+        4, 0x00, 0x00, 0x00, 0x60, 0x90, 0x90, 0x70, 0x10, 0x60, // 'g'
+        4, 0x00, 0x80, 0x80, 0xE0, 0x90, 0x90, 0x90, 0x00, 0x00, // 'h'
+        3, 0x00, 0x40, 0x00, 0xC0, 0x40, 0x40, 0xE0, 0x00, 0x00, // 'i'
+        3, 0x00, 0x20, 0x00, 0x60, 0x20, 0x20, 0x20, 0xA0, 0x40, // 'j'
+        4, 0x00, 0x80, 0x80, 0xA0, 0xC0, 0xA0, 0x90, 0x00, 0x00, // 'k'
+        3, 0x00, 0xC0, 0x40, 0x40, 0x40, 0x40, 0xE0, 0x00, 0x00, // 'l'
+        5, 0x00, 0x00, 0x00, 0xD0, 0xA8, 0xA8, 0x88, 0x00, 0x00, // 'm'
+        4, 0x00, 0x00, 0x00, 0xE0, 0x90, 0x90, 0x90, 0x00, 0x00, // 'n'
+        4, 0x00, 0x00, 0x00, 0x60, 0x90, 0x90, 0x60, 0x00, 0x00, // 'o'
+        4, 0x00, 0x00, 0x00, 0xE0, 0x90, 0x90, 0xE0, 0x80, 0x80, // 'p'
+        4, 0x00, 0x00, 0x00, 0x70, 0x90, 0x90, 0x70, 0x10, 0x10, // 'q'
+        4, 0x00, 0x00, 0x00, 0xA0, 0xD0, 0x80, 0x80, 0x00, 0x00, // 'r'
+        4, 0x00, 0x00, 0x00, 0x70, 0xC0, 0x30, 0xE0, 0x00, 0x00, // 's'
+        4, 0x00, 0x40, 0x40, 0xE0, 0x40, 0x50, 0x20, 0x00, 0x00, // 't'
+        4, 0x00, 0x00, 0x00, 0x90, 0x90, 0x90, 0x70, 0x00, 0x00, // 'u'
+        4, 0x00, 0x00, 0x00, 0x90, 0x90, 0x60, 0x60, 0x00, 0x00, // 'v'
+        5, 0x00, 0x00, 0x00, 0x88, 0xA8, 0xA8, 0x50, 0x00, 0x00, // 'w'
+        4, 0x00, 0x00, 0x00, 0x90, 0x60, 0x60, 0x90, 0x00, 0x00, // 'x'
+        4, 0x00, 0x00, 0x00, 0x90, 0x90, 0x90, 0x70, 0x90, 0x60, // 'y'
+        4, 0x00, 0x00, 0x00, 0xF0, 0x20, 0x40, 0xF0, 0x00, 0x00, // 'z'
+        3, 0x20, 0x40, 0x40, 0x80, 0x40, 0x40, 0x20, 0x00, 0x00, // '{'
+        1, 0x00, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00, // '|'
+        3, 0x80, 0x40, 0x40, 0x20, 0x40, 0x40, 0x80, 0x00, 0x00, // '}'
+        4, 0x00, 0x00, 0x50, 0xA0, 0x00, 0x00, 0x00, 0x00, 0x00, // '~'
+    ],
+    first_char: ' ',
+    height: 9,
+    baseline: 6,
+    spacing: 1,
+};
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::convert::Infallible;
+    use std::vec::Vec;
+
+    use embedded_graphics::prelude::{OriginDimensions, Size};
+
+    use super::*;
+
+    /// Collects drawn pixels
+    struct Canvas(Vec<Point>);
+
+    impl OriginDimensions for Canvas {
+        fn size(&self) -> Size {
+            Size::new(64, 32)
+        }
+    }
+
+    impl DrawTarget for Canvas {
+        type Color = BinaryColor;
+        type Error = Infallible;
+
+        fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+        where
+            I: IntoIterator<Item = Pixel<Self::Color>>,
+        {
+            self.0
+                .extend(pixels.into_iter().map(|Pixel(point, _)| point));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn glyphs_advance_by_their_width_and_spacing() {
+        let text_width: u32 = "Hi!".chars().map(|c| NARROW_6X9.advance(c)).sum();
+
+        assert_eq!(NARROW_6X9.advance('i'), 4);
+        assert_eq!(NARROW_6X9.advance('m'), 6);
+        assert_eq!(text_width, 5 + 4 + 2);
+    }
+
+    #[test]
+    fn characters_outside_the_font_take_the_place_of_question_mark() {
+        assert_eq!(NARROW_6X9.advance('é'), NARROW_6X9.advance('?'));
+        assert_eq!(NARROW_6X9.advance('\n'), NARROW_6X9.advance('?'));
+    }
+
+    #[test]
+    fn glyph_is_drawn_above_the_baseline() {
+        let mut canvas = Canvas(Vec::new());
+        NARROW_6X9
+            .draw_char('!', Point::new(10, 20), &mut canvas)
+            .unwrap();
+
+        //Rows 1 to 4 and 6 of the glyph, which starts 6 rows above the baseline
+        let expected: Vec<Point> = [15, 16, 17, 18, 20]
+            .iter()
+            .map(|y| Point::new(10, *y))
+            .collect();
+        assert_eq!(canvas.0, expected);
+    }
+}
@@ -0,0 +1,201 @@
+//! Layout of a block of data stored with its version, length and crc, so it's ignored
+//! after layout change, interrupted write or when nothing was saved yet
+
+use crate::DisplayError;
+
+//version, body length and crc
+pub const HEADER_LEN: usize = 6;
+
+/// Header written in front of a body of the given length and crc, see crc16_ccitt
+pub fn header(version: u16, length: usize, crc: u16) -> [u8; HEADER_LEN] {
+    let mut header = [0; HEADER_LEN];
+    header[0..2].copy_from_slice(&version.to_le_bytes());
+    header[2..4].copy_from_slice(&(length as u16).to_le_bytes());
+    header[4..6].copy_from_slice(&crc.to_le_bytes());
+
+    header
+}
+
+/// Returns the body of the record stored in bytes if it has the version,
+/// fits and its crc matches
+pub fn body(bytes: &[u8], version: u16) -> Option<&[u8]> {
+    if bytes.len() < HEADER_LEN {
+        return None;
+    }
+
+    let read_u16 = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+    let length = read_u16(2) as usize;
+    let crc = read_u16(4);
+
+    if read_u16(0) != version || length > bytes.len() - HEADER_LEN {
+        return None;
+    }
+
+    let data = &bytes[HEADER_LEN..HEADER_LEN + length];
+    if crc16_ccitt(0xFFFF, data) != crc {
+        return None;
+    }
+
+    Some(data)
+}
+
+//CRC-16/CCITT, continues from crc, so longer data can be processed in parts
+pub fn crc16_ccitt(crc: u16, data: &[u8]) -> u16 {
+    let mut val = crc;
+
+    for byte in data {
+        val ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            if val & 0x8000 != 0 {
+                val = (val << 1) ^ 0x1021;
+            } else {
+                val <<= 1;
+            }
+        }
+    }
+
+    val
+}
+
+/// Takes data of a record in the order it's read back by RecordReader
+pub trait Sink {
+    fn push(&mut self, data: &[u8]) -> Result<(), DisplayError>;
+
+    fn push_u16(&mut self, value: u16) -> Result<(), DisplayError> {
+        self.push(&value.to_le_bytes())
+    }
+}
+
+/// Reads saved data in the order it was pushed, every read returns None
+/// when the data ends prematurely
+pub struct RecordReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> RecordReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        RecordReader { data }
+    }
+
+    pub fn read(&mut self, length: usize) -> Option<&'a [u8]> {
+        if length > self.data.len() {
+            return None;
+        }
+
+        let (bytes, rest) = self.data.split_at(length);
+        self.data = rest;
+
+        Some(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Option<u8> {
+        self.read(1).map(|bytes| bytes[0])
+    }
+
+    pub fn read_u16(&mut self) -> Option<u16> {
+        self.read(2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use super::*;
+
+    /// Sink of a record with the given capacity, as RecordWriter refuses data beyond it
+    pub struct Body {
+        pub data: Vec<u8>,
+        pub capacity: usize,
+    }
+
+    impl Body {
+        pub fn new(capacity: usize) -> Self {
+            Body {
+                data: Vec::new(),
+                capacity,
+            }
+        }
+    }
+
+    impl Sink for Body {
+        fn push(&mut self, data: &[u8]) -> Result<(), DisplayError> {
+            if self.data.len() + data.len() > self.capacity {
+                return Err(DisplayError::OutOfBounds);
+            }
+
+            self.data.extend_from_slice(data);
+            Ok(())
+        }
+    }
+
+    //Record of 64 bytes in erased flash
+    fn stored(version: u16, data: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::from(&header(version, data.len(), crc16_ccitt(0xFFFF, data))[..]);
+        bytes.extend_from_slice(data);
+        bytes.resize(64, 0xFF);
+
+        bytes
+    }
+
+    #[test]
+    fn stored_body_is_read_back() {
+        let bytes = stored(3, b"saved data");
+
+        assert_eq!(body(&bytes, 3), Some(&b"saved data"[..]));
+    }
+
+    #[test]
+    fn erased_record_has_no_body() {
+        assert_eq!(body(&[0xFF; 64], 1), None);
+        assert_eq!(body(&[0xFF; 64], 0xFFFF), None);
+    }
+
+    #[test]
+    fn body_of_another_version_is_ignored() {
+        let bytes = stored(2, b"old layout");
+
+        assert_eq!(body(&bytes, 3), None);
+    }
+
+    #[test]
+    fn corrupted_body_is_ignored() {
+        let mut bytes = stored(1, b"saved data");
+        bytes[HEADER_LEN + 4] ^= 0x10;
+
+        assert_eq!(body(&bytes, 1), None);
+    }
+
+    #[test]
+    fn length_beyond_the_record_is_ignored() {
+        let mut bytes = stored(1, b"saved data");
+        bytes[2..4].copy_from_slice(&59u16.to_le_bytes());
+
+        assert_eq!(body(&bytes, 1), None);
+        assert_eq!(body(&bytes[..3], 1), None);
+    }
+
+    #[test]
+    fn crc_continues_over_parts() {
+        let whole = crc16_ccitt(0xFFFF, b"123456789");
+        let parts = crc16_ccitt(crc16_ccitt(0xFFFF, b"1234"), b"56789");
+
+        //CRC-16/CCITT-FALSE check value
+        assert_eq!(whole, 0x29B1);
+        assert_eq!(parts, whole);
+    }
+
+    #[test]
+    fn reads_stop_at_the_end_of_data() {
+        let mut reader = RecordReader::new(&[1, 2, 3, 4]);
+
+        assert_eq!(reader.read_u8(), Some(1));
+        assert_eq!(reader.read_u16(), Some(0x0302));
+        assert_eq!(reader.read(2), None);
+        assert_eq!(reader.read_u8(), Some(4));
+        assert_eq!(reader.read_u8(), None);
+    }
+}
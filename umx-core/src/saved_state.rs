@@ -0,0 +1,258 @@
+//! Layout of the state stored by SaveState: the mode followed by its text rows
+//! or the color bits of the framebuffer
+
+use crate::{
+    record::{RecordReader, Sink},
+    DisplayError,
+};
+
+pub const TEXT_MODE: u8 = 0;
+pub const DIRECT_MODE: u8 = 1;
+
+/// Settings and text of a row of text mode, ids and encodings as in the commands setting them
+pub struct SavedRow<'a> {
+    pub font: u8,
+    pub text_color: [u8; 3],
+    /// None is transparent
+    pub background: Option<[u8; 3]>,
+    pub animation: [u8; 3],
+    pub color_effect: [u8; 7],
+    pub text: &'a str,
+}
+
+impl<'a> SavedRow<'a> {
+    pub fn save<S: Sink>(&self, sink: &mut S) -> Result<(), DisplayError> {
+        //Same as SetBackground, 1 is transparent
+        let background = match self.background {
+            Some([r, g, b]) => [0, r, g, b],
+            None => [1, 0, 0, 0],
+        };
+
+        sink.push(&[self.font])?;
+        sink.push(&self.text_color)?;
+        sink.push(&background)?;
+        sink.push(&self.animation)?;
+        sink.push(&self.color_effect)?;
+
+        sink.push_u16(self.text.len() as u16)?;
+        sink.push(self.text.as_bytes())
+    }
+
+    /// Returns None if saved data is incomplete or the text is longer than max_length
+    pub fn restore(reader: &mut RecordReader<'a>, max_length: usize) -> Option<Self> {
+        let font = reader.read_u8()?;
+        let text_color = read_array(reader)?;
+        let background: [u8; 4] = read_array(reader)?;
+        let animation = read_array(reader)?;
+        let color_effect = read_array(reader)?;
+
+        let length = reader.read_u16()? as usize;
+        if length > max_length {
+            return None;
+        }
+        let text = core::str::from_utf8(reader.read(length)?).ok()?;
+
+        Some(SavedRow {
+            font,
+            text_color,
+            background: match background[0] {
+                0 => Some([background[1], background[2], background[3]]),
+                _ => None,
+            },
+            animation,
+            color_effect,
+            text,
+        })
+    }
+}
+
+/// Bytes taken by save_framebuffer
+pub fn framebuffer_len(words: usize) -> usize {
+    //word count, then color bits of 4 words packed into 3 bytes
+    2 + (words + 3) / 4 * 3
+}
+
+/// Stores 6 color bits of every word returned by color_bits
+pub fn save_framebuffer<S, F>(sink: &mut S, words: usize, color_bits: F) -> Result<(), DisplayError>
+where
+    S: Sink,
+    F: Fn(usize) -> u8,
+{
+    sink.push_u16(words as u16)?;
+
+    for index in (0..words).step_by(4) {
+        let mut packed = 0u32;
+        for i in 0..4 {
+            if index + i < words {
+                packed |= (color_bits(index + i) as u32) << (6 * i);
+            }
+        }
+
+        sink.push(&packed.to_le_bytes()[..3])?;
+    }
+
+    Ok(())
+}
+
+/// Passes color bits of every word to set_color_bits.
+/// Fails without setting any if the framebuffer was saved with another number of words
+pub fn restore_framebuffer<F>(
+    reader: &mut RecordReader,
+    words: usize,
+    mut set_color_bits: F,
+) -> Option<()>
+where
+    F: FnMut(usize, u8),
+{
+    if reader.read_u16()? as usize != words {
+        return None;
+    }
+
+    let data = reader.read(framebuffer_len(words) - 2)?;
+
+    for (chunk, bytes) in data.chunks(3).enumerate() {
+        let packed = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]);
+
+        for i in 0..4 {
+            let index = chunk * 4 + i;
+            if index < words {
+                set_color_bits(index, (packed >> (6 * i)) as u8 & 0x3F);
+            }
+        }
+    }
+
+    Some(())
+}
+
+fn read_array<const N: usize>(reader: &mut RecordReader) -> Option<[u8; N]> {
+    let mut array = [0; N];
+    array.copy_from_slice(reader.read(N)?);
+
+    Some(array)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::record::tests::Body;
+
+    fn row(text: &str, background: Option<[u8; 3]>) -> SavedRow {
+        SavedRow {
+            font: 7,
+            text_color: [255, 128, 0],
+            background,
+            animation: [2, 4, 1],
+            color_effect: [1, 10, 20, 30, 40, 50, 60],
+            text,
+        }
+    }
+
+    fn assert_same(restored: &SavedRow, saved: &SavedRow) {
+        assert_eq!(restored.font, saved.font);
+        assert_eq!(restored.text_color, saved.text_color);
+        assert_eq!(restored.background, saved.background);
+        assert_eq!(restored.animation, saved.animation);
+        assert_eq!(restored.color_effect, saved.color_effect);
+        assert_eq!(restored.text, saved.text);
+    }
+
+    #[test]
+    fn rows_are_restored_as_saved() {
+        let rows = [row("Zażółć", Some([0, 0, 64])), row("", None)];
+        let mut body = Body::new(1024);
+        for row in rows.iter() {
+            row.save(&mut body).unwrap();
+        }
+
+        let mut reader = RecordReader::new(&body.data);
+        for row in rows.iter() {
+            assert_same(&SavedRow::restore(&mut reader, 256).unwrap(), row);
+        }
+        assert_eq!(reader.read_u8(), None);
+    }
+
+    #[test]
+    fn black_background_is_not_transparent() {
+        let mut body = Body::new(1024);
+        row("A", Some([0, 0, 0])).save(&mut body).unwrap();
+
+        let restored = SavedRow::restore(&mut RecordReader::new(&body.data), 256).unwrap();
+        assert_eq!(restored.background, Some([0, 0, 0]));
+    }
+
+    #[test]
+    fn row_is_not_restored_from_incomplete_data() {
+        let mut body = Body::new(1024);
+        row("Hello", None).save(&mut body).unwrap();
+
+        for length in 0..body.data.len() {
+            let mut reader = RecordReader::new(&body.data[..length]);
+            assert!(SavedRow::restore(&mut reader, 256).is_none());
+        }
+    }
+
+    #[test]
+    fn text_longer_than_a_row_is_not_restored() {
+        let mut body = Body::new(1024);
+        row("Hello", None).save(&mut body).unwrap();
+
+        assert!(SavedRow::restore(&mut RecordReader::new(&body.data), 4).is_none());
+    }
+
+    #[test]
+    fn text_that_is_not_utf8_is_not_restored() {
+        let mut body = Body::new(1024);
+        row("Hé", None).save(&mut body).unwrap();
+        //cuts é in half
+        let last = body.data.len() - 1;
+        body.data[last] = b'e';
+
+        assert!(SavedRow::restore(&mut RecordReader::new(&body.data), 256).is_none());
+    }
+
+    #[test]
+    fn framebuffer_is_restored_as_saved() {
+        //Not a multiple of 4, the last 3 bytes hold 2 words
+        let words = 10;
+        let bits: Vec<u8> = (0..words).map(|i| (i * 13 % 64) as u8).collect();
+
+        let mut body = Body::new(1024);
+        save_framebuffer(&mut body, words, |i| bits[i]).unwrap();
+        assert_eq!(body.data.len(), framebuffer_len(words));
+
+        let mut restored = std::vec![0xFF; words];
+        restore_framebuffer(&mut RecordReader::new(&body.data), words, |i, b| {
+            restored[i] = b
+        })
+        .unwrap();
+        assert_eq!(restored, bits);
+    }
+
+    #[test]
+    fn framebuffer_of_another_size_is_not_restored() {
+        let mut body = Body::new(1024);
+        save_framebuffer(&mut body, 8, |_| 0x3F).unwrap();
+
+        let mut set = 0;
+        let restored = restore_framebuffer(&mut RecordReader::new(&body.data), 12, |_, _| set += 1);
+        assert!(restored.is_none());
+        assert_eq!(set, 0);
+
+        let truncated = &body.data[..body.data.len() - 1];
+        assert!(restore_framebuffer(&mut RecordReader::new(truncated), 8, |_, _| ()).is_none());
+    }
+
+    #[test]
+    fn framebuffer_that_does_not_fit_is_refused() {
+        let mut body = Body::new(framebuffer_len(64) - 1);
+
+        assert_eq!(
+            save_framebuffer(&mut body, 64, |_| 0),
+            Err(DisplayError::OutOfBounds)
+        );
+    }
+}
@@ -0,0 +1,250 @@
+//! Queue of framed responses for transports that can't take a whole response at once,
+//! e.g. USB CDC accepting only what fits into its endpoint buffer or a UART taking one byte
+//! whenever its transmit register is empty.
+//!
+//! Tests run on the host: `cargo test -p umx-tx --target x86_64-unknown-linux-gnu`
+
+#![no_std]
+
+const HEADER: [u8; 3] = [85, 77, 88];
+//header, length and command code before the response, crc after it
+const FRAME_OVERHEAD: usize = HEADER.len() + 2 + 1 + 1;
+
+/// The frame doesn't fit into free space of the queue
+#[derive(Debug, PartialEq)]
+pub struct QueueFull;
+
+/// Ring buffer of frames waiting to be written, frames are queued whole or not at all
+pub struct TxQueue<const N: usize> {
+    buffer: [u8; N],
+    //index of the oldest byte
+    start: usize,
+    len: usize,
+}
+
+impl<const N: usize> TxQueue<N> {
+    pub const fn new() -> Self {
+        TxQueue {
+            buffer: [0; N],
+            start: 0,
+            len: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Bytes waiting to be written
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Drops everything not written yet, e.g. when the host disconnects
+    pub fn clear(&mut self) {
+        self.start = 0;
+        self.len = 0;
+    }
+
+    /// Queues the response of a command with header, length, command code and crc
+    pub fn push_frame(&mut self, command: u8, response: &[u8], crc: u8) -> Result<(), QueueFull> {
+        if FRAME_OVERHEAD + response.len() > N - self.len {
+            return Err(QueueFull);
+        }
+
+        //command code and response
+        let length = (response.len() + 1) as u16;
+
+        self.push(&HEADER);
+        self.push(&length.to_be_bytes());
+        self.push(&[command]);
+        self.push(response);
+        self.push(&[crc]);
+
+        Ok(())
+    }
+
+    fn push(&mut self, data: &[u8]) {
+        for byte in data {
+            self.buffer[(self.start + self.len) % N] = *byte;
+            self.len += 1;
+        }
+    }
+
+    /// Writes queued bytes until write blocks or the queue is empty.
+    ///
+    /// write returns how many bytes of the given slice it took, the rest is retried
+    /// on the next flush. Queued data stays untouched if write fails
+    pub fn flush<E, F>(&mut self, mut write: F) -> Result<(), E>
+    where
+        F: FnMut(&[u8]) -> nb::Result<usize, E>,
+    {
+        while self.len > 0 {
+            //up to the end of the buffer, the rest follows from its beginning
+            let end = (self.start + self.len).min(N);
+
+            match write(&self.buffer[self.start..end]) {
+                Ok(0) | Err(nb::Error::WouldBlock) => break,
+                Ok(count) => {
+                    let count = count.min(end - self.start);
+                    self.start = (self.start + count) % N;
+                    self.len -= count;
+                }
+                Err(nb::Error::Other(error)) => return Err(error),
+            }
+        }
+
+        if self.len == 0 {
+            self.start = 0;
+        }
+
+        Ok(())
+    }
+}
+
+impl<const N: usize> Default for TxQueue<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    const MAX_PACKET_SIZE: usize = 64;
+
+    /// Model of a CDC serial port: writes fill a buffer of limited size,
+    /// the host takes it out in packets
+    struct CdcEndpoint {
+        buffer: Vec<u8>,
+        capacity: usize,
+        received: Vec<u8>,
+        blocked: usize,
+    }
+
+    impl CdcEndpoint {
+        fn new(capacity: usize) -> Self {
+            CdcEndpoint {
+                buffer: Vec::new(),
+                capacity,
+                received: Vec::new(),
+                blocked: 0,
+            }
+        }
+
+        fn write(&mut self, data: &[u8]) -> nb::Result<usize, ()> {
+            let free = self.capacity - self.buffer.len();
+            if free == 0 {
+                self.blocked += 1;
+                return Err(nb::Error::WouldBlock);
+            }
+
+            let count = free.min(data.len());
+            self.buffer.extend_from_slice(&data[..count]);
+
+            Ok(count)
+        }
+
+        /// Host reads one packet, returns its size
+        fn transfer(&mut self) -> usize {
+            let count = self.buffer.len().min(MAX_PACKET_SIZE);
+            self.received.extend(self.buffer.drain(..count));
+
+            count
+        }
+    }
+
+    fn frame(command: u8, response: &[u8], crc: u8) -> Vec<u8> {
+        let length = (response.len() + 1) as u16;
+
+        let mut frame = Vec::from(&b"UMX"[..]);
+        frame.extend_from_slice(&length.to_be_bytes());
+        frame.push(command);
+        frame.extend_from_slice(response);
+        frame.push(crc);
+
+        frame
+    }
+
+    /// Flushes the queue and transfers packets until everything reaches the host
+    fn deliver<const N: usize>(queue: &mut TxQueue<N>, endpoint: &mut CdcEndpoint) {
+        loop {
+            queue.flush(|data| endpoint.write(data)).unwrap();
+
+            let packet = endpoint.transfer();
+            assert!(packet <= MAX_PACKET_SIZE);
+
+            if packet == 0 && queue.is_empty() {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn response_is_sent_once_in_a_frame() {
+        let mut queue = TxQueue::<512>::new();
+        let mut endpoint = CdcEndpoint::new(128);
+
+        queue.push_frame(15, b"PONG\n", 0x5A).unwrap();
+        deliver(&mut queue, &mut endpoint);
+
+        assert_eq!(endpoint.received, frame(15, b"PONG\n", 0x5A));
+    }
+
+    #[test]
+    fn long_response_is_split_into_packets_under_backpressure() {
+        let mut queue = TxQueue::<512>::new();
+        let mut endpoint = CdcEndpoint::new(128);
+
+        let response: Vec<u8> = (0..384).map(|i| i as u8).collect();
+        queue.push_frame(35, &response, 0x11).unwrap();
+        deliver(&mut queue, &mut endpoint);
+
+        assert!(endpoint.blocked > 0);
+        assert_eq!(endpoint.received, frame(35, &response, 0x11));
+    }
+
+    #[test]
+    fn frames_wrapping_around_stay_in_order() {
+        let mut queue = TxQueue::<64>::new();
+        let mut endpoint = CdcEndpoint::new(48);
+        let mut expected = Vec::new();
+
+        for command in 0..20u8 {
+            let response: Vec<u8> = (0..command as usize * 2).map(|i| i as u8).collect();
+            queue.push_frame(command, &response, command).unwrap();
+            expected.extend(frame(command, &response, command));
+
+            //leaves part of every frame in the queue
+            queue.flush(|data| endpoint.write(data)).unwrap();
+            endpoint.transfer();
+        }
+        deliver(&mut queue, &mut endpoint);
+
+        assert_eq!(endpoint.received, expected);
+    }
+
+    #[test]
+    fn frame_that_does_not_fit_is_not_queued() {
+        let mut queue = TxQueue::<64>::new();
+
+        queue.push_frame(1, &[0; 40], 0).unwrap();
+        let queued = queue.len();
+
+        assert_eq!(queue.push_frame(2, &[0; 20], 0), Err(QueueFull));
+        assert_eq!(queue.len(), queued);
+    }
+
+    #[test]
+    fn write_error_keeps_queued_data() {
+        let mut queue = TxQueue::<64>::new();
+        queue.push_frame(1, b"OK\n", 0).unwrap();
+
+        assert_eq!(queue.flush(|_| Err(nb::Error::Other("gone"))), Err("gone"));
+        assert_eq!(queue.len(), 10);
+    }
+}